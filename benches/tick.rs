@@ -0,0 +1,39 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use tjr_chip8::chip8::{Chip8, Config, Platform};
+
+/// A busy loop touching the common opcode groups, including an FX33 store so the decode cache
+/// pays for its invalidation on every iteration.
+const LOOP_ROM: [u8; 20] = [
+    0x60, 0x00, // 0x200: v0 := 0x00
+    0x70, 0x01, // 0x202: v0 += 0x01
+    0x81, 0x04, // 0x204: v1 += v0
+    0x82, 0x13, // 0x206: v2 ^= v1
+    0x32, 0x00, // 0x208: if v2 != 0x00 then
+    0x63, 0x01, // 0x20A: v3 := 0x01
+    0xA3, 0x00, // 0x20C: i := 0x300
+    0xF0, 0x33, // 0x20E: bcd v0
+    0xD0, 0x15, // 0x210: sprite v0 v1 5
+    0x12, 0x02, // 0x212: jump 0x202
+];
+
+const INSTRUCTIONS: usize = 10_000;
+
+fn run(config: Config) -> Chip8 {
+    let mut cpu = Chip8::new(&LOOP_ROM, config).unwrap();
+    for _ in 0..INSTRUCTIONS {
+        cpu.tick(black_box([false; 16])).unwrap();
+    }
+    cpu
+}
+
+fn tick_benchmark(c: &mut Criterion) {
+    let plain = Config::new(Platform::Chip8);
+    let cached = Config { decode_cache: true, ..plain };
+    let mut group = c.benchmark_group("tick");
+    group.bench_function("decode every fetch", |b| b.iter(|| run(plain)));
+    group.bench_function("decode cache", |b| b.iter(|| run(cached)));
+    group.finish();
+}
+
+criterion_group!(benches, tick_benchmark);
+criterion_main!(benches);
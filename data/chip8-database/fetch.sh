@@ -0,0 +1,15 @@
+#!/bin/sh
+# Replaces the bundled programs.json, sha1-hashes.json and platforms.json with a snapshot of
+# the community CHIP-8 database. Pass a commit or tag to pin the snapshot; review the diff and
+# commit it like any other change.
+set -eu
+
+REF="${1:-master}"
+BASE="https://raw.githubusercontent.com/chip-8/chip-8-database/$REF/database"
+DIR="$(dirname "$0")"
+
+for FILE in programs.json sha1-hashes.json platforms.json; do
+    curl --fail --silent --show-error --location "$BASE/$FILE" --output "$DIR/$FILE.tmp"
+    mv "$DIR/$FILE.tmp" "$DIR/$FILE"
+done
+echo "Fetched the community database at $REF into $DIR"
@@ -1,31 +1,157 @@
-use iced::{button, executor, keyboard, slider, time,
+use iced::{executor, keyboard, time,
            Application,Clipboard, Command, Element, Subscription};
 use rodio::{
     source::{SineWave, Source},
     Sink,
 };
-use std::time::Duration;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use nfd2::Response;
 
+use tjr_chip8::assembler;
+use tjr_chip8::chip8;
+use tjr_chip8::database::{RomDatabase, RomInfo};
 use crate::gui;
-use crate::chip8;
 use crate::keypad;
+use crate::save_slots::{self, SaveSlot};
 
 //CONSTANTS
 const DEFAULT_CLOCK_SPEED: u32 = 800;
+/// The range of the clock speed slider on the main menu, in instructions per second.
+pub const MIN_CLOCK_SPEED: u32 = 100;
+pub const MAX_CLOCK_SPEED: u32 = 5000;
+const DEFAULT_REWIND_MEMORY_MB: u32 = 16;
+/// Frames between rewind snapshots; holding the rewind key steps back this many frames at a time.
+const REWIND_INTERVAL: u32 = 2;
+/// Wall-clock time uncapped fast-forward may spend emulating per displayed frame, leaving the
+/// rest of the 16.7 ms for drawing and input.
+const UNCAPPED_FRAME_BUDGET: Duration = Duration::from_millis(12);
 const APPLICATION_TITLE: &str = "CHIP-8";
+/// A checkout of the community ROM database in the working directory takes precedence over the
+/// copy bundled with the emulator.
+const DATABASE_DIRECTORY: &str = "chip8-database";
 
 //ICED STATE
 pub struct Chip8Emulator {
     gui: gui::Gui,
     keyboard: keypad::Keyboard,
     chip8: Option<chip8::Chip8>,
+    rom: Option<Vec<u8>>,
+    rom_path: Option<PathBuf>,
+    /// The ROM `chip8` was built from, to tell whether launching again can just resume it.
+    launched_rom: Option<Vec<u8>>,
+    database: RomDatabase,
+    scheduler: chip8::FrameScheduler,
     settings: Chip8EmulatorSettings,
+    slots: Vec<SaveSlot>,
+    selected_slot: usize,
+    rewind: chip8::RewindBuffer,
+    rewinding: bool,
+    slow_motion: SlowMotion,
+    /// Display frames since the last emulated frame, for slow motion.
+    skipped_frames: u32,
+    fast_forward_held: bool,
+    fast_forward_toggled: bool,
+    /// The address run to cursor stops at.
+    debug_cursor: Option<u16>,
+    /// The page of memory the debugger's hex view shows.
+    debug_memory_page: usize,
+    /// Where a step over, step out or run to cursor stops; it runs at normal speed until then.
+    run_target: Option<chip8::RunTarget>,
+}
+
+/// Runs one emulated frame every `divisor` display frames, so CPU and timers slow down together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlowMotion {
+    Off,
+    Half,
+    Quarter,
+    Eighth,
+}
+
+impl SlowMotion {
+    pub const ALL: [SlowMotion; 4] = [SlowMotion::Off, SlowMotion::Half, SlowMotion::Quarter, SlowMotion::Eighth];
+
+    pub fn divisor(&self) -> u32 {
+        match self {
+            SlowMotion::Off => 1,
+            SlowMotion::Half => 2,
+            SlowMotion::Quarter => 4,
+            SlowMotion::Eighth => 8,
+        }
+    }
+
+    fn next(&self) -> Self {
+        match self {
+            SlowMotion::Off => SlowMotion::Half,
+            SlowMotion::Half => SlowMotion::Quarter,
+            SlowMotion::Quarter => SlowMotion::Eighth,
+            SlowMotion::Eighth => SlowMotion::Off,
+        }
+    }
+}
+
+/// How many emulated frames run per displayed frame while fast-forwarding. Whole frames are run,
+/// so the timers speed up along with the CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FastForward {
+    Double,
+    Quadruple,
+    Octuple,
+    /// As many frames as fit in `UNCAPPED_FRAME_BUDGET`.
+    Uncapped,
+}
+
+impl FastForward {
+    pub const ALL: [FastForward; 4] = [FastForward::Double, FastForward::Quadruple, FastForward::Octuple, FastForward::Uncapped];
+}
+
+/// A machine address, shown in hex on the main menu.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Address(pub u16);
+
+impl Address {
+    /// The usual 0x200, and 0x600 for ETI-660 programs.
+    pub const LOAD_ADDRESSES: [Address; 2] = [Address(0x200), Address(0x600)];
+    /// 0x000 as in most modern interpreters, and 0x050 where many others put it.
+    pub const FONT_ADDRESSES: [Address; 2] = [Address(0x000), Address(0x050)];
+}
+
+impl std::fmt::Display for Address {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "0x{:03X}", self.0)
+    }
+}
+
+impl std::fmt::Display for FastForward {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FastForward::Double => write!(f, "2x"),
+            FastForward::Quadruple => write!(f, "4x"),
+            FastForward::Octuple => write!(f, "8x"),
+            FastForward::Uncapped => write!(f, "Uncapped"),
+        }
+    }
+}
+
+impl std::fmt::Display for SlowMotion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SlowMotion::Off => write!(f, "Full speed"),
+            _ => write!(f, "1/{} speed", self.divisor()),
+        }
+    }
 }
 
 pub struct Chip8EmulatorSettings {
     pub rom_name: String,
+    /// What the ROM database knows about the chosen ROM, if it recognised it.
+    pub rom_info: Option<RomInfo>,
     pub clock_speed: u32,
+    pub timing: chip8::TimingMode,
+    pub rewind_memory_mb: u32,
+    pub fast_forward: FastForward,
+    pub config: chip8::Config,
 }
 
 #[derive(Debug, Clone)]
@@ -34,21 +160,61 @@ pub enum Message {
     Goto(gui::PageModel),
     ChooseRom,
     ClockSpeedChanged(i32),
-    BgRedChanged(i32),
-    BgGreenChanged(i32),
-    BgBlueChanged(i32),
-    FgRedChanged(i32),
-    FgGreenChanged(i32),
-    FgBlueChanged(i32),
-    CpuClockTick,
-    TimerClockTick,
+    /// Fast instructions-per-frame pacing or per-instruction COSMAC VIP cycle costs.
+    TimingModeSelected(chip8::TimingMode),
+    RewindMemoryChanged(i32),
+    /// Palette entry, RGB channel and the new channel value.
+    PaletteChanged(usize, usize, i32),
+    PlatformSelected(chip8::Platform),
+    MemorySizeSelected(usize),
+    StackDepthSelected(usize),
+    LoadAddressSelected(Address),
+    FontSelected(chip8::FontSet),
+    FontAddressSelected(Address),
+    ChooseFont,
+    QuirksPresetSelected(chip8::QuirksPreset),
+    ShiftQuirkToggled(bool),
+    LoadStoreQuirkToggled(bool),
+    JumpQuirkToggled(bool),
+    VfResetQuirkToggled(bool),
+    WrapQuirkToggled(bool),
+    /// One 60 Hz frame: a batch of instructions, a timer decrement and at most one redraw.
+    Frame,
+    TogglePause,
+    /// Run exactly one frame while paused.
+    FrameAdvance,
+    SlowMotionSelected(SlowMotion),
+    FastForwardSelected(FastForward),
+    /// Restart the current ROM from its power-on state.
+    Reset,
+    /// Read the ROM file from disk again and restart it, e.g. after rebuilding it.
+    Reload,
+    SlotSelected(SaveSlot),
+    SaveState,
+    LoadState,
+    /// Execute one instruction in the debugger.
+    DebugStep,
+    /// Step, running a subroutine call to completion.
+    DebugStepOver,
+    /// Run until the current subroutine returns.
+    DebugStepOut,
+    DebugRunToCursor,
+    DebugCursorSelected(u16),
+    DebugMemoryPage(usize),
+    /// Leave the debugger and resume normal execution.
+    DebugContinue,
 }
 
 impl Chip8EmulatorSettings {
     pub fn new() -> Self {
         Self {
             rom_name: String::from(""),
+            rom_info: None,
             clock_speed: DEFAULT_CLOCK_SPEED,
+            timing: chip8::TimingMode::Fast,
+            rewind_memory_mb: DEFAULT_REWIND_MEMORY_MB,
+            fast_forward: FastForward::Quadruple,
+            config: chip8::Config::default(),
         }
     }
 }
@@ -59,11 +225,347 @@ impl Default for Chip8Emulator {
             gui: gui::Gui::new(),
             keyboard: keypad::Keyboard::new(),
             chip8: None,
+            rom: None,
+            rom_path: None,
+            launched_rom: None,
+            database: RomDatabase::load(Path::new(DATABASE_DIRECTORY)).unwrap_or_else(|_| RomDatabase::bundled()),
+            scheduler: chip8::FrameScheduler::new(DEFAULT_CLOCK_SPEED),
             settings: Chip8EmulatorSettings::new(),
+            slots: Vec::new(),
+            selected_slot: 0,
+            rewind: chip8::RewindBuffer::new(DEFAULT_REWIND_MEMORY_MB as usize << 20, REWIND_INTERVAL),
+            rewinding: false,
+            slow_motion: SlowMotion::Off,
+            skipped_frames: 0,
+            fast_forward_held: false,
+            fast_forward_toggled: false,
+            debug_cursor: None,
+            debug_memory_page: 0,
+            run_target: None,
         }
     }
 }
 
+impl Chip8Emulator {
+    /// Switches to the emulation screen, resuming the current interpreter if it runs the chosen
+    /// ROM with the current settings and building a fresh one otherwise.
+    fn launch(&mut self) {
+        //Returning from the menu without changing anything picks up where the game left off
+        if let (Some(chip8), Some(rom)) = (&self.chip8, &self.rom) {
+            let config = chip8::Config { seed: chip8.config().seed, ..self.settings.config };
+            if self.launched_rom.as_ref() == Some(rom) && *chip8.config() == config {
+                self.gui.error = None;
+                self.gui.current_page = gui::PageModel::emulation_screen();
+                return;
+            }
+        }
+        self.restart();
+    }
+
+    /// Builds a fresh interpreter for the chosen ROM even if the current one could be resumed.
+    fn restart(&mut self) {
+        //The config is fixed for the lifetime of a Chip8, so build it only once it is final
+        if let Some(rom) = &self.rom {
+            //The core is deterministic by default; games should still differ between runs
+            let mut config = self.settings.config;
+            config.seed = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(chip8::DEFAULT_SEED);
+            match chip8::Chip8::new(rom, config) {
+                Ok(chip8) => {
+                    self.chip8 = Some(chip8);
+                    self.launched_rom = Some(rom.clone());
+                    self.rewind.clear();
+                    self.gui.error = None;
+                    self.gui.current_page = gui::PageModel::emulation_screen();
+                    self.debug_cursor = None;
+                    self.run_target = None;
+                    self.slots = save_slots::list(&self.settings.rom_name);
+                },
+                Err(e) => self.gui.error = Some(e.to_string()),
+            }
+        }
+    }
+
+    /// Looks the chosen ROM up in the database and applies the platform, quirks, start address,
+    /// speed, game keys and colours it recommends. Unknown ROMs keep the current settings.
+    fn apply_rom_info(&mut self) {
+        self.settings.rom_info = self.rom.as_ref().and_then(|rom| self.database.lookup(rom));
+        let info = match &self.settings.rom_info {
+            Some(info) => info,
+            None => {
+                self.keyboard = keypad::Keyboard::new();
+                return
+            },
+        };
+        if let Some(config) = info.config() {
+            //Keep the user's font choices; the database does not describe fonts
+            self.settings.config = chip8::Config {
+                font: self.settings.config.font,
+                font_address: self.settings.config.font_address,
+                ..config
+            };
+        }
+        if let Some(tickrate) = info.tickrate {
+            //Keep within the slider, which would otherwise jump on the next touch
+            self.settings.clock_speed = tickrate.saturating_mul(chip8::FRAME_RATE).clamp(MIN_CLOCK_SPEED, MAX_CLOCK_SPEED);
+            self.scheduler.set_clock_speed(self.settings.clock_speed);
+        }
+        for (entry, color) in self.gui.screen.palette.iter_mut().zip(info.palette.iter()) {
+            *entry = [color[0] as u32, color[1] as u32, color[2] as u32];
+        }
+        self.gui.screen.screen.clear();
+        self.keyboard.map_game_keys(&info.keys);
+    }
+
+    fn toggle_pause(&mut self) {
+        if let Some(chip8) = &mut self.chip8 {
+            if chip8.status == chip8::CpuStatus::Paused {
+                chip8.resume();
+            } else {
+                chip8.pause();
+            }
+        }
+    }
+
+    fn fast_forwarding(&self) -> bool {
+        self.fast_forward_held || self.fast_forward_toggled
+    }
+
+    fn run_fast_forward(&mut self) {
+        let frames = match self.settings.fast_forward {
+            FastForward::Double => 2,
+            FastForward::Quadruple => 4,
+            FastForward::Octuple => 8,
+            FastForward::Uncapped => {
+                let start = Instant::now();
+                while start.elapsed() < UNCAPPED_FRAME_BUDGET {
+                    self.run_frame();
+                    match &self.chip8 {
+                        Some(chip8) if chip8.status == chip8::CpuStatus::Running || chip8.status.is_waiting_for_key() => (),
+                        _ => break,
+                    }
+                }
+                return;
+            },
+        };
+        for _ in 0..frames {
+            self.run_frame();
+        }
+    }
+
+    fn advance_frame(&mut self) {
+        match &mut self.chip8 {
+            Some(chip8) if chip8.status == chip8::CpuStatus::Paused => chip8.resume(),
+            _ => return,
+        }
+        self.run_frame();
+        if let Some(chip8) = &mut self.chip8 {
+            chip8.pause();
+        }
+    }
+
+    /// Pauses the interpreter and shows its state on the debugger page.
+    fn open_debugger(&mut self) {
+        match &mut self.chip8 {
+            Some(chip8) => {
+                chip8.pause();
+                self.debug_memory_page = gui::debugger::memory_page(chip8.pc());
+            },
+            None => return,
+        }
+        self.run_target = None;
+        self.rewinding = false;
+        self.fast_forward_held = false;
+        self.gui.current_page = gui::PageModel::debugger();
+    }
+
+    /// Executes a single instruction of the paused interpreter. Timers only count down once per
+    /// frame, so they stay as they are.
+    fn debug_step(&mut self) {
+        let chip8 = match &mut self.chip8 {
+            Some(chip8) if chip8.status == chip8::CpuStatus::Paused => chip8,
+            _ => return,
+        };
+        chip8.resume();
+        let result = chip8.tick(self.keyboard.keys);
+        chip8.pause();
+        match result {
+            Ok(()) => self.gui.error = None,
+            Err(e) => self.gui.error = Some(format!("Emulation halted: {}", e)),
+        }
+        self.refresh_screen();
+    }
+
+    /// Starts running towards `target`, one frame at a time so the program keeps its normal
+    /// speed.
+    fn debug_run(&mut self, target: chip8::RunTarget) {
+        self.gui.error = None;
+        self.run_target = Some(target);
+    }
+
+    fn debug_step_over(&mut self) {
+        match self.chip8.as_ref().and_then(chip8::RunTarget::step_over) {
+            Some(target) => self.debug_run(target),
+            None => self.debug_step(),
+        }
+    }
+
+    fn debug_step_out(&mut self) {
+        if let Some(target) = self.chip8.as_ref().and_then(chip8::RunTarget::step_out) {
+            self.debug_run(target);
+        }
+    }
+
+    /// One frame of a run towards `run_target`, pausing again once it is reached or the
+    /// interpreter stops.
+    fn run_debug_frame(&mut self) {
+        let target = match (self.run_target, &mut self.chip8) {
+            (Some(target), Some(chip8)) => {
+                chip8.resume();
+                target
+            },
+            _ => return,
+        };
+        self.run_frame();
+        if let Some(chip8) = &mut self.chip8 {
+            let stopped = chip8.status == chip8::CpuStatus::Halted || chip8.status == chip8::CpuStatus::Exited;
+            if stopped || target.reached(chip8) {
+                self.run_target = None;
+            }
+            chip8.pause();
+        }
+    }
+
+    fn debug_continue(&mut self) {
+        self.run_target = None;
+        if let Some(chip8) = &mut self.chip8 {
+            chip8.resume();
+        }
+        self.gui.current_page = gui::PageModel::emulation_screen();
+    }
+
+    fn reset(&mut self) {
+        if let Some(chip8) = &mut self.chip8 {
+            chip8.reset();
+            self.rewind.clear();
+            self.gui.error = None;
+        }
+    }
+
+    fn reload(&mut self) {
+        let path = match &self.rom_path {
+            Some(path) => path,
+            None => return,
+        };
+        match read_rom(path) {
+            Ok(rom) => {
+                self.rom = Some(rom);
+                self.restart();
+            },
+            Err(e) => self.gui.error = Some(format!("Unable to reload ROM: {}", e)),
+        }
+    }
+
+    fn goto_main_menu(&mut self) {
+        self.rewinding = false;
+        self.run_target = None;
+        self.fast_forward_held = false;
+        self.gui.current_page = gui::PageModel::main_menu(self.settings.clock_speed);
+    }
+
+    fn save_slot(&mut self, index: usize) {
+        let chip8 = match &self.chip8 {
+            Some(chip8) => chip8,
+            None => return,
+        };
+        match save_slots::write(&self.settings.rom_name, index, &chip8.save_state()) {
+            Ok(()) => self.gui.error = None,
+            Err(e) => self.gui.error = Some(format!("Unable to save slot {}: {}", index + 1, e)),
+        }
+        self.selected_slot = index;
+        self.slots = save_slots::list(&self.settings.rom_name);
+    }
+
+    fn load_slot(&mut self, index: usize) {
+        let chip8 = match &mut self.chip8 {
+            Some(chip8) => chip8,
+            None => return,
+        };
+        let result = save_slots::read(&self.settings.rom_name, index)
+            .map_err(|e| e.to_string())
+            .and_then(|state| chip8.load_state(&state).map_err(|e| e.to_string()));
+        match result {
+            Ok(()) => {
+                self.gui.error = None;
+                self.rewind.clear();
+            },
+            Err(e) => self.gui.error = Some(format!("Unable to load slot {}: {}", index + 1, e)),
+        }
+        self.selected_slot = index;
+    }
+
+    /// Copies the framebuffer to the GUI if the program drew since the last copy.
+    fn refresh_screen(&mut self) {
+        if let Some(chip8) = &mut self.chip8 {
+            if chip8.redraw {
+                let display = chip8.screen();
+                self.gui.screen.width = display.width();
+                self.gui.screen.height = display.height();
+                self.gui.screen.pixels = display.pixels().to_vec();
+                self.gui.screen.screen.clear();
+                chip8.redraw = false;
+            }
+        }
+    }
+
+    fn run_frame(&mut self) {
+        let chip8 = match &mut self.chip8 {
+            Some(chip8) => chip8,
+            None => return,
+        };
+        let sound_before = chip8.sound_timer();
+        if self.rewinding {
+            if let Err(e) = self.rewind.rewind(chip8) {
+                self.gui.error = Some(format!("Unable to rewind: {}", e));
+            }
+        } else {
+            if let Err(e) = self.scheduler.run_frame_until(chip8, self.keyboard.keys, self.run_target) {
+                self.gui.error = Some(format!("Emulation halted: {}", e));
+            }
+            if chip8.status != chip8::CpuStatus::Paused {
+                self.rewind.record(chip8);
+            }
+        }
+        if chip8.status == chip8::CpuStatus::Exited {
+            self.gui.error = Some(String::from("Program exited"));
+        }
+        //Start one tone lasting as long as the timer will, rather than a tone per frame
+        let sound_after = chip8.sound_timer();
+        self.refresh_screen();
+        //Tones would only stutter at fast-forward speeds, so stay silent
+        if sound_after > sound_before && !(self.fast_forward_held || self.fast_forward_toggled) {
+            let duration = Duration::from_millis(sound_after as u64 * 1000 * self.slow_motion.divisor() as u64 / chip8::FRAME_RATE as u64);
+            std::thread::spawn(move || {
+                let (_stream, stream_handle) = rodio::OutputStream::try_default().unwrap();
+                let sink = Sink::try_new(&stream_handle).unwrap();
+                let source = SineWave::new(800).take_duration(duration);
+                sink.append(source);
+                sink.play();
+                sink.sleep_until_end();
+            });
+        }
+    }
+}
+
+/// Reads the ROM at `path`, assembling it first if it is an Octo `.8o` source.
+fn read_rom(path: &Path) -> Result<Vec<u8>, String> {
+    let is_source = matches!(path.extension(), Some(extension) if extension.eq_ignore_ascii_case("8o"));
+    if !is_source {
+        return std::fs::read(path).map_err(|e| e.to_string());
+    }
+    let source = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    assembler::assemble(&source).map_err(|e| e.to_string())
+}
+
     impl Application for Chip8Emulator {
         type Executor = executor::Default;
         type Message = Message;
@@ -82,10 +584,14 @@ impl Default for Chip8Emulator {
     }
 
     fn view(&mut self) -> Element<Message> {
-        match &mut self.gui.current_page {
-            gui::PageModel::MainMenu { .. } => self.gui.make(&self.settings),
-            gui::PageModel::EmulationScreen => self.gui.make(&self.settings),
-        }
+        let paused = self.chip8.as_ref().map_or(false, |chip8| chip8.status == chip8::CpuStatus::Paused);
+        let session = self.chip8.as_ref().map(|chip8| gui::debugger::Session {
+            chip8,
+            cursor: self.debug_cursor,
+            memory_page: self.debug_memory_page,
+            running: self.run_target.is_some(),
+        });
+        self.gui.make(&self.settings, paused, self.slow_motion, &self.slots, self.selected_slot, session)
     }
 
     fn update(&mut self, message: Message, _clipboard: &mut Clipboard) -> Command<Message> {
@@ -96,8 +602,16 @@ impl Default for Chip8Emulator {
                     gui::PageModel::MainMenu { .. } => {
                         match nfd2::open_file_dialog(None, None).expect("Unable to open file dialog") {
                             Response::Okay(file_path) => {
-                                let rom_path = file_path.clone().into_os_string().into_string().unwrap();
-                                self.chip8 = Some(chip8::Chip8::new(rom_path));
+                                match read_rom(&file_path) {
+                                    Ok(rom) => self.rom = Some(rom),
+                                    Err(e) => {
+                                        self.rom = None;
+                                        self.gui.error = Some(format!("Unable to load ROM: {}", e));
+                                        return Command::none();
+                                    },
+                                }
+                                self.gui.error = None;
+                                self.rom_path = Some(file_path.clone());
                                 match file_path.file_name() {
                                     Some(x) => {
                                         self.settings.rom_name = x.to_os_string().into_string().unwrap();
@@ -105,6 +619,7 @@ impl Default for Chip8Emulator {
                                     },
                                     None => ()
                                 };
+                                self.apply_rom_info();
                             },
                             _ => println!("User canceled")
                         }
@@ -114,35 +629,56 @@ impl Default for Chip8Emulator {
             },
             Message::Goto(p) => {
                 match p {
-                    gui::PageModel::MainMenu { .. } => {
-                        self.gui.current_page = gui::PageModel::MainMenu {
-                            clock_speed_state: slider::State::new(),
-                            clock_speed_value: self.settings.clock_speed,
-                            bg_red_state: slider::State::new(),
-                            bg_red_value: self.gui.screen.bg_red,
-                            bg_green_state: slider::State::new(),
-                            bg_green_value: self.gui.screen.bg_green,
-                            bg_blue_state: slider::State::new(),
-                            bg_blue_value: self.gui.screen.bg_blue,
-                            fg_red_state: slider::State::new(),
-                            fg_red_value: self.gui.screen.bg_red,
-                            fg_green_state: slider::State::new(),
-                            fg_green_value: self.gui.screen.bg_green,
-                            fg_blue_state: slider::State::new(),
-                            fg_blue_value: self.gui.screen.bg_blue,
-                            choose_rom_button: button::State::new(),
-                            launch_button: button::State::new()
-                        };
-                    },
-                    gui::PageModel::EmulationScreen => {
-                        self.gui.current_page = gui::PageModel::EmulationScreen;
-                    },
+                    gui::PageModel::MainMenu { .. } => self.goto_main_menu(),
+                    gui::PageModel::EmulationScreen { .. } => self.launch(),
+                    gui::PageModel::Debugger { .. } => self.open_debugger(),
                 }
             },
             Message::IcedEvent(event) => {
                 match event {
                     iced_native::Event::Keyboard(keyboard_event) => match keyboard_event {
-                        keyboard::Event::KeyPressed { key_code, .. } => {
+                        keyboard::Event::KeyPressed { key_code, modifiers } => {
+                            if let gui::PageModel::EmulationScreen { .. } = self.gui.current_page {
+                                let slot = match key_code {
+                                    keyboard::KeyCode::F1 => Some(0),
+                                    keyboard::KeyCode::F2 => Some(1),
+                                    keyboard::KeyCode::F3 => Some(2),
+                                    keyboard::KeyCode::F4 => Some(3),
+                                    _ => None,
+                                };
+                                match slot {
+                                    Some(index) if modifiers.shift => self.save_slot(index),
+                                    Some(index) => self.load_slot(index),
+                                    None => (),
+                                }
+                                match key_code {
+                                    keyboard::KeyCode::Backspace => self.rewinding = true,
+                                    keyboard::KeyCode::P => self.toggle_pause(),
+                                    keyboard::KeyCode::F6 => self.reset(),
+                                    keyboard::KeyCode::F7 => self.reload(),
+                                    keyboard::KeyCode::F8 => self.advance_frame(),
+                                    keyboard::KeyCode::F9 => self.slow_motion = self.slow_motion.next(),
+                                    keyboard::KeyCode::Tab => self.fast_forward_held = true,
+                                    keyboard::KeyCode::Grave => self.fast_forward_toggled = !self.fast_forward_toggled,
+                                    keyboard::KeyCode::F12 => self.open_debugger(),
+                                    keyboard::KeyCode::Escape => self.goto_main_menu(),
+                                    _ => (),
+                                }
+                            } else if let gui::PageModel::Debugger { .. } = self.gui.current_page {
+                                if self.run_target.is_none() {
+                                    match key_code {
+                                        keyboard::KeyCode::F10 => self.debug_step(),
+                                        keyboard::KeyCode::F11 if modifiers.shift => self.debug_step_out(),
+                                        keyboard::KeyCode::F11 => self.debug_step_over(),
+                                        _ => (),
+                                    }
+                                }
+                                match key_code {
+                                    keyboard::KeyCode::F5 => self.debug_continue(),
+                                    keyboard::KeyCode::Escape => self.goto_main_menu(),
+                                    _ => (),
+                                }
+                            }
                             match self.keyboard.key_map.get(&key_code) {
                                 Some(k) => {
                                     self.keyboard.keys[*k] = true;
@@ -151,6 +687,11 @@ impl Default for Chip8Emulator {
                             }
                         },
                         keyboard::Event::KeyReleased { key_code, .. } => {
+                            match key_code {
+                                keyboard::KeyCode::Backspace => self.rewinding = false,
+                                keyboard::KeyCode::Tab => self.fast_forward_held = false,
+                                _ => (),
+                            }
                             match self.keyboard.key_map.get(&key_code) {
                                 Some(k) => {
                                     self.keyboard.keys[*k] = false;
@@ -163,52 +704,82 @@ impl Default for Chip8Emulator {
                     _ => ()
                 }
             },
-            Message::CpuClockTick => {
-                match &mut self.chip8 {
-                    Some(chip8) => {
-                        match chip8.status {
-                            chip8::CpuStatus::Running => {
-                                chip8.tick(self.keyboard.keys);
-                                if chip8.redraw {
-                                    self.gui.screen.pixels = chip8.screen;
-                                    self.gui.screen.screen.clear();
-                                    chip8.redraw = false;
-                                }
-                                if chip8.sound > 0 {
-                                    std::thread::spawn(move || {
-                                        let (_stream, stream_handle) = rodio::OutputStream::try_default().unwrap();
-                                        let sink = Sink::try_new(&stream_handle).unwrap();
-                                        let source = SineWave::new(800).take_duration(Duration::from_millis(167));
-                                        sink.append(source);
-                                        sink.play();
-                                        sink.sleep_until_end();
-                                    });
-                                }
-                            }
-                            _ => ()
-                        }
-                    },
-                    None => ()
+            Message::Frame => {
+                if let gui::PageModel::EmulationScreen { .. } = self.gui.current_page {
+                    self.skipped_frames += 1;
+                    if self.fast_forwarding() && !self.rewinding {
+                        self.skipped_frames = 0;
+                        self.run_fast_forward();
+                    } else if self.skipped_frames >= self.slow_motion.divisor() {
+                        self.skipped_frames = 0;
+                        self.run_frame();
+                    }
+                } else if let gui::PageModel::Debugger { .. } = self.gui.current_page {
+                    self.run_debug_frame();
                 }
             },
-            Message::TimerClockTick => {
-                match &mut self.chip8 {
-                    Some(chip8) => {
-                        match chip8.status {
-                            chip8::CpuStatus::Running => chip8.decrement_counters(),
-                            _ => ()
-                        }
-                    },
-                    None => ()
+            Message::TogglePause => self.toggle_pause(),
+            Message::FrameAdvance => self.advance_frame(),
+            Message::SlowMotionSelected(slow_motion) => self.slow_motion = slow_motion,
+            Message::FastForwardSelected(fast_forward) => self.settings.fast_forward = fast_forward,
+            Message::Reset => self.reset(),
+            Message::Reload => self.reload(),
+            Message::SlotSelected(slot) => self.selected_slot = slot.index,
+            Message::SaveState => self.save_slot(self.selected_slot),
+            Message::LoadState => self.load_slot(self.selected_slot),
+            Message::DebugStep => self.debug_step(),
+            Message::DebugStepOver => self.debug_step_over(),
+            Message::DebugStepOut => self.debug_step_out(),
+            Message::DebugRunToCursor => {
+                if let Some(address) = self.debug_cursor {
+                    self.debug_run(chip8::RunTarget::run_to(address));
                 }
             },
-            Message::ClockSpeedChanged(val) => self.settings.clock_speed = val as u32,
-            Message::BgRedChanged(val) => self.gui.screen.bg_red = val as u32,
-            Message::BgGreenChanged(val) => self.gui.screen.bg_green = val as u32,
-            Message::BgBlueChanged(val) => self.gui.screen.bg_blue = val as u32,
-            Message::FgRedChanged(val) => self.gui.screen.fg_red = val as u32,
-            Message::FgGreenChanged(val) => self.gui.screen.fg_green = val as u32,
-            Message::FgBlueChanged(val) => self.gui.screen.fg_blue = val as u32,
+            Message::DebugCursorSelected(address) => self.debug_cursor = Some(address),
+            Message::DebugMemoryPage(page) => self.debug_memory_page = page,
+            Message::DebugContinue => self.debug_continue(),
+            Message::ClockSpeedChanged(val) => {
+                self.settings.clock_speed = val as u32;
+                self.scheduler.set_clock_speed(self.settings.clock_speed);
+            },
+            Message::RewindMemoryChanged(val) => {
+                self.settings.rewind_memory_mb = val as u32;
+                self.rewind.set_budget(self.settings.rewind_memory_mb as usize << 20);
+            },
+            Message::TimingModeSelected(timing) => {
+                self.settings.timing = timing;
+                self.scheduler.set_timing(timing);
+            },
+            Message::PaletteChanged(color, channel, val) => {
+                self.gui.screen.palette[color][channel] = val as u32;
+                self.gui.screen.screen.clear();
+            },
+            Message::PlatformSelected(platform) => self.settings.config.set_platform(platform),
+            Message::MemorySizeSelected(size) => self.settings.config.memory_size = size,
+            Message::StackDepthSelected(depth) => self.settings.config.stack_depth = depth,
+            Message::LoadAddressSelected(address) => self.settings.config.load_address = address.0,
+            Message::FontSelected(font) => self.settings.config.font = font,
+            Message::FontAddressSelected(address) => self.settings.config.font_address = address.0,
+            Message::ChooseFont => {
+                if let Response::Okay(file_path) = nfd2::open_file_dialog(None, None).expect("Unable to open file dialog") {
+                    let font = std::fs::read(&file_path)
+                        .map_err(|e| e.to_string())
+                        .and_then(|bytes| chip8::FontSet::parse(&bytes).map_err(|e| e.to_string()));
+                    match font {
+                        Ok(font) => {
+                            self.settings.config.font = font;
+                            self.gui.error = None;
+                        },
+                        Err(e) => self.gui.error = Some(format!("Unable to load font: {}", e)),
+                    }
+                }
+            },
+            Message::QuirksPresetSelected(preset) => self.settings.config.quirks = preset.quirks(),
+            Message::ShiftQuirkToggled(val) => self.settings.config.quirks.shift_reads_vy = val,
+            Message::LoadStoreQuirkToggled(val) => self.settings.config.quirks.load_store_increments_i = val,
+            Message::JumpQuirkToggled(val) => self.settings.config.quirks.jump_uses_vx = val,
+            Message::VfResetQuirkToggled(val) => self.settings.config.quirks.logic_resets_vf = val,
+            Message::WrapQuirkToggled(val) => self.settings.config.quirks.wrap_sprites = val,
         }
         Command::none()
     }
@@ -216,14 +787,10 @@ impl Default for Chip8Emulator {
     fn subscription(&self) -> Subscription<Self::Message> {
         let runtime_events = iced_native::subscription::events().map(Message::IcedEvent);
 
-        let ticks = time::every(Duration::from_millis(
-            1000 / self.settings.clock_speed as u64,
-        )).map(|_| -> Message { Message::CpuClockTick });
-
-        let timers = time::every(Duration::from_millis(
-            1000 / 60 as u64,
-        )).map(|_| -> Message { Message::TimerClockTick });
+        let frames = time::every(Duration::from_nanos(
+            1_000_000_000 / chip8::FRAME_RATE as u64,
+        )).map(|_| -> Message { Message::Frame });
 
-        Subscription::batch(vec![runtime_events, ticks, timers])
+        Subscription::batch(vec![runtime_events, frames])
     }
 }
\ No newline at end of file
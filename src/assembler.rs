@@ -0,0 +1,805 @@
+//! An assembler for Octo, the usual language for writing CHIP-8, SUPER-CHIP and XO-CHIP
+//! programs. It supports labels, `:const`, `:alias`, `:calc`, macros, structured `if`/`loop`
+//! blocks and the XO-CHIP extensions, and produces a ROM image to be loaded at 0x200.
+//!
+//! ```
+//! let rom = tjr_chip8::assembler::assemble(": main  v0 := 5  loop again").unwrap();
+//! assert_eq!(rom, [0x60, 0x05, 0x12, 0x02]);
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::chip8::constants::{MAX_MEMORY_SIZE, PC_INITIAL};
+
+mod calc;
+mod lexer;
+
+use lexer::Token;
+
+/// Macro expansions allowed per program, so a macro that invokes itself fails instead of hanging.
+const MAX_EXPANSIONS: usize = 100_000;
+
+/// Why a program could not be assembled, and where. Lines and columns start at 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssemblyError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for AssemblyError {}
+
+/// Assembles Octo source into a ROM image that starts at 0x200. If the program defines `main`
+/// anywhere but at the start, the image begins with a jump to it.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssemblyError> {
+    let tokens = lexer::tokenize(source);
+    let assembler = Assembler::run(&tokens, false)?;
+    match assembler.labels.get("main") {
+        Some(&main) if main != PC_INITIAL => Ok(Assembler::run(&tokens, true)?.rom),
+        _ => Ok(assembler.rom),
+    }
+}
+
+/// Parses a decimal, `0x` hexadecimal or `0b` binary number, optionally negative.
+fn number(text: &str) -> Option<f64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()? as f64
+    } else if let Some(binary) = digits.strip_prefix("0b").or_else(|| digits.strip_prefix("0B")) {
+        i64::from_str_radix(binary, 2).ok()? as f64
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn error(token: &Token, message: impl Into<String>) -> AssemblyError {
+    AssemblyError { line: token.line, column: token.column, message: message.into() }
+}
+
+const KEYWORDS: [&str; 45] = [
+    ":", ":alias", ":const", ":calc", ":macro", ":org", ":unpack", ":next", ":byte", ":pointer",
+    ":call", ":breakpoint", ":monitor", "clear", "return", ";", "hires", "lores", "exit",
+    "scroll-down", "scroll-up", "scroll-left", "scroll-right", "bcd", "save", "load", "saveflags",
+    "loadflags", "sprite", "jump", "jump0", "audio", "plane", "delay", "buzzer", "pitch", "i",
+    "if", "then", "begin", "else", "end", "loop", "while", "again",
+];
+
+/// Where a forward reference has to be patched once its label is defined.
+#[derive(Debug, Clone, Copy)]
+enum Patch {
+    /// The 12-bit operand of the instruction at the address.
+    Address,
+    /// A 16-bit big-endian value at the address.
+    Word,
+    /// The `:unpack` pair of instructions at the address, with the nibble to combine.
+    Unpack(u8),
+}
+
+struct Fixup {
+    address: usize,
+    patch: Patch,
+    name: String,
+    token: Token,
+}
+
+struct Macro {
+    parameters: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// A `loop` waiting for its `again`, with the `while` jumps that leave it.
+struct Loop {
+    start: usize,
+    token: Token,
+    exits: Vec<usize>,
+}
+
+struct Assembler {
+    /// Remaining tokens, last token first, so macro bodies can be pushed in front.
+    pending: Vec<Token>,
+    /// The last token taken, for errors at the end of the source.
+    last: Token,
+    expansions: usize,
+    rom: Vec<u8>,
+    here: usize,
+    labels: HashMap<String, u16>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    loops: Vec<Loop>,
+    /// Open `if ... begin` blocks: the address of the jump to patch at `else` or `end`.
+    branches: Vec<(usize, Token)>,
+}
+
+impl calc::Scope for Assembler {
+    fn lookup(&self, name: &str) -> Option<f64> {
+        match name {
+            "HERE" => Some(self.here as f64),
+            "PI" => Some(std::f64::consts::PI),
+            "E" => Some(std::f64::consts::E),
+            _ => self.constants.get(name).copied().or_else(|| self.labels.get(name).map(|&a| a as f64)),
+        }
+    }
+
+    fn peek(&self, address: f64) -> Option<f64> {
+        let offset = (address as usize).checked_sub(PC_INITIAL as usize)?;
+        self.rom.get(offset).map(|&byte| byte as f64)
+    }
+}
+
+impl Assembler {
+    fn run(tokens: &[Token], jump_to_main: bool) -> Result<Self, AssemblyError> {
+        let mut assembler = Self {
+            pending: tokens.iter().rev().cloned().collect(),
+            last: tokens.first().cloned().unwrap_or(Token { text: String::new(), line: 1, column: 1 }),
+            expansions: 0,
+            rom: Vec::new(),
+            here: PC_INITIAL as usize,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            loops: Vec::new(),
+            branches: Vec::new(),
+        };
+        if jump_to_main {
+            let token = assembler.last.clone();
+            let name = String::from("main");
+            assembler.fixups.push(Fixup { address: assembler.here, patch: Patch::Address, name, token });
+            assembler.emit_word(0x1000)?;
+        }
+        while !assembler.pending.is_empty() {
+            assembler.statement()?;
+        }
+        if let Some(open) = assembler.loops.last() {
+            return Err(error(&open.token, "'loop' without 'again'"));
+        }
+        if let Some((_, token)) = assembler.branches.last() {
+            return Err(error(token, "'begin' without 'end'"));
+        }
+        for fixup in std::mem::take(&mut assembler.fixups) {
+            let value = match assembler.labels.get(&fixup.name) {
+                Some(&value) => value,
+                None => return Err(error(&fixup.token, format!("undefined name '{}'", fixup.name))),
+            };
+            assembler.apply(&fixup, value)?;
+        }
+        Ok(assembler)
+    }
+
+    fn next(&mut self) -> Result<Token, AssemblyError> {
+        match self.pending.pop() {
+            Some(token) => {
+                self.last = token.clone();
+                Ok(token)
+            },
+            None => Err(error(&self.last, format!("unexpected end of program after '{}'", self.last.text))),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.pending.last().map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, text: &str) -> Result<Token, AssemblyError> {
+        let token = self.next()?;
+        if token.text == text {
+            Ok(token)
+        } else {
+            Err(error(&token, format!("expected '{}', found '{}'", text, token.text)))
+        }
+    }
+
+    fn emit(&mut self, byte: u8) -> Result<(), AssemblyError> {
+        if self.here >= MAX_MEMORY_SIZE {
+            return Err(error(&self.last, "program does not fit in 64 KiB"));
+        }
+        let offset = self.here - PC_INITIAL as usize;
+        if offset >= self.rom.len() {
+            self.rom.resize(offset + 1, 0);
+        }
+        self.rom[offset] = byte;
+        self.here += 1;
+        Ok(())
+    }
+
+    fn emit_word(&mut self, word: u16) -> Result<(), AssemblyError> {
+        self.emit((word >> 8) as u8)?;
+        self.emit(word as u8)
+    }
+
+    fn apply(&mut self, fixup: &Fixup, value: u16) -> Result<(), AssemblyError> {
+        let offset = fixup.address - PC_INITIAL as usize;
+        match fixup.patch {
+            Patch::Address => {
+                if value > 0xFFF {
+                    return Err(error(&fixup.token, format!("address 0x{:X} of '{}' is beyond 0xFFF", value, fixup.name)));
+                }
+                self.rom[offset] |= (value >> 8) as u8;
+                self.rom[offset + 1] = value as u8;
+            },
+            Patch::Word => {
+                self.rom[offset] = (value >> 8) as u8;
+                self.rom[offset + 1] = value as u8;
+            },
+            Patch::Unpack(nibble) => {
+                if value > 0xFFF {
+                    return Err(error(&fixup.token, format!("address 0x{:X} of '{}' is beyond 0xFFF", value, fixup.name)));
+                }
+                self.rom[offset + 1] = (nibble << 4) | (value >> 8) as u8;
+                self.rom[offset + 3] = value as u8;
+            },
+        }
+        Ok(())
+    }
+
+    /// The value of a number or defined name.
+    fn value(&mut self, token: &Token) -> Result<f64, AssemblyError> {
+        calc::Scope::lookup(self, &token.text)
+            .filter(|_| !token.text.starts_with(|c: char| c.is_ascii_digit() || c == '-'))
+            .or_else(|| number(&token.text))
+            .ok_or_else(|| error(token, format!("undefined name '{}'", token.text)))
+    }
+
+    fn ranged(&mut self, token: &Token, min: i64, max: i64, what: &str) -> Result<i64, AssemblyError> {
+        let value = self.value(token)?.floor() as i64;
+        if value < min || value > max {
+            return Err(error(token, format!("{} must be between {} and {}, got {}", what, min, max, value)));
+        }
+        Ok(value)
+    }
+
+    fn byte(&mut self) -> Result<u8, AssemblyError> {
+        let token = self.next()?;
+        Ok(self.ranged(&token, -128, 255, "a byte")? as u8)
+    }
+
+    fn nibble(&mut self) -> Result<u8, AssemblyError> {
+        let token = self.next()?;
+        Ok(self.ranged(&token, 0, 15, "a nibble")? as u8)
+    }
+
+    fn is_register(&self, text: &str) -> bool {
+        self.aliases.contains_key(text) || Self::register_index(text).is_some()
+    }
+
+    fn register_index(text: &str) -> Option<u8> {
+        let mut chars = text.chars();
+        match (chars.next(), chars.next(), chars.next()) {
+            (Some('v'), Some(digit), None) | (Some('V'), Some(digit), None) => digit.to_digit(16).map(|d| d as u8),
+            _ => None,
+        }
+    }
+
+    fn register(&mut self) -> Result<u8, AssemblyError> {
+        let token = self.next()?;
+        self.aliases.get(&token.text).copied()
+            .or_else(|| Self::register_index(&token.text))
+            .ok_or_else(|| error(&token, format!("expected a register, found '{}'", token.text)))
+    }
+
+    /// The value of `token` if it is a number or an already defined name. Otherwise `token` must
+    /// be a label defined further on, and the code about to be emitted is patched when it is.
+    fn reference(&mut self, token: &Token, patch: Patch) -> Result<Option<f64>, AssemblyError> {
+        if let Some(value) = calc::Scope::lookup(self, &token.text).or_else(|| number(&token.text)) {
+            return Ok(Some(value));
+        }
+        self.check_name(token)?;
+        self.fixups.push(Fixup { address: self.here, patch, name: token.text.clone(), token: token.clone() });
+        Ok(None)
+    }
+
+    /// Emits `opcode` with the 12-bit address `token` refers to.
+    fn address_operand(&mut self, opcode: u16, token: &Token) -> Result<(), AssemblyError> {
+        match self.reference(token, Patch::Address)? {
+            Some(value) => {
+                let value = Self::unsigned(token, value, 0xFFF, "an address")?;
+                self.emit_word(opcode | value)
+            },
+            None => self.emit_word(opcode),
+        }
+    }
+
+    fn check_name(&self, token: &Token) -> Result<(), AssemblyError> {
+        let text = token.text.as_str();
+        if number(text).is_some() || Self::register_index(text).is_some() || KEYWORDS.contains(&text)
+            || "{}()".contains(text) {
+            return Err(error(token, format!("'{}' cannot be used as a name", text)));
+        }
+        Ok(())
+    }
+
+    fn define(&mut self, token: &Token) -> Result<String, AssemblyError> {
+        self.check_name(token)?;
+        if self.labels.contains_key(&token.text) || self.constants.contains_key(&token.text) {
+            return Err(error(token, format!("'{}' is already defined", token.text)));
+        }
+        Ok(token.text.clone())
+    }
+
+    /// The tokens between a `{` and its matching `}`.
+    fn block(&mut self) -> Result<Vec<Token>, AssemblyError> {
+        self.expect("{")?;
+        let mut depth = 1;
+        let mut body = Vec::new();
+        loop {
+            let token = self.next()?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(body);
+                    }
+                },
+                _ => (),
+            }
+            body.push(token);
+        }
+    }
+
+    fn calc(&mut self) -> Result<f64, AssemblyError> {
+        let tokens = self.block()?;
+        calc::evaluate(&tokens, self).map_err(|(token, message)| error(&token, message))
+    }
+
+    fn statement(&mut self) -> Result<(), AssemblyError> {
+        let token = self.next()?;
+        match token.text.as_str() {
+            ":" => {
+                let name_token = self.next()?;
+                let name = self.define(&name_token)?;
+                self.labels.insert(name, self.here as u16);
+            },
+            ":next" => {
+                let name_token = self.next()?;
+                let name = self.define(&name_token)?;
+                self.labels.insert(name, self.here as u16 + 1);
+            },
+            ":const" => {
+                let name_token = self.next()?;
+                let name = self.define(&name_token)?;
+                let value_token = self.next()?;
+                let value = self.value(&value_token)?;
+                self.constants.insert(name, value);
+            },
+            ":calc" => {
+                let name_token = self.next()?;
+                self.check_name(&name_token)?;
+                if self.labels.contains_key(&name_token.text) {
+                    return Err(error(&name_token, format!("'{}' is already a label", name_token.text)));
+                }
+                //Unlike :const, :calc may redefine a constant, e.g. to count in a macro
+                let value = self.calc()?;
+                self.constants.insert(name_token.text, value);
+            },
+            ":alias" => {
+                let name_token = self.next()?;
+                self.check_name(&name_token)?;
+                let register = self.register()?;
+                self.aliases.insert(name_token.text, register);
+            },
+            ":macro" => {
+                let name_token = self.next()?;
+                self.check_name(&name_token)?;
+                let mut parameters = Vec::new();
+                while !matches!(self.peek(), Some("{") | None) {
+                    parameters.push(self.next()?.text);
+                }
+                let body = self.block()?;
+                self.macros.insert(name_token.text, Macro { parameters, body });
+            },
+            ":org" => {
+                let address_token = self.next()?;
+                let address = self.ranged(&address_token, PC_INITIAL as i64, MAX_MEMORY_SIZE as i64 - 1, "an :org address")?;
+                self.here = address as usize;
+            },
+            ":byte" => {
+                let value = if self.peek() == Some("{") {
+                    let value_token = self.pending.last().cloned().unwrap();
+                    let value = self.calc()?.floor() as i64;
+                    if !(-128..=255).contains(&value) {
+                        return Err(error(&value_token, format!("a byte must be between -128 and 255, got {}", value)));
+                    }
+                    value as u8
+                } else {
+                    self.byte()?
+                };
+                self.emit(value)?;
+            },
+            ":pointer" => {
+                let name_token = self.next()?;
+                let value = self.reference(&name_token, Patch::Word)?.unwrap_or(0.0);
+                self.emit_word(Self::unsigned(&name_token, value, 0xFFFF, "a pointer")?)?;
+            },
+            ":unpack" => {
+                let nibble = self.nibble()?;
+                let name_token = self.next()?;
+                let value = self.reference(&name_token, Patch::Unpack(nibble))?.unwrap_or(0.0);
+                let value = Self::unsigned(&name_token, value, 0xFFF, "an address")?;
+                self.emit_word(0x6000 | (nibble as u16) << 4 | value >> 8)?;
+                self.emit_word(0x6100 | (value & 0xFF))?;
+            },
+            ":call" => {
+                let target = self.next()?;
+                self.address_operand(0x2000, &target)?;
+            },
+            ":breakpoint" => {
+                self.next()?;
+            },
+            ":monitor" => {
+                self.next()?;
+                self.next()?;
+            },
+            "clear" => self.emit_word(0x00E0)?,
+            "return" | ";" => self.emit_word(0x00EE)?,
+            "hires" => self.emit_word(0x00FF)?,
+            "lores" => self.emit_word(0x00FE)?,
+            "exit" => self.emit_word(0x00FD)?,
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit_word(0x00C0 | n as u16)?;
+            },
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit_word(0x00D0 | n as u16)?;
+            },
+            "scroll-left" => self.emit_word(0x00FC)?,
+            "scroll-right" => self.emit_word(0x00FB)?,
+            "audio" => self.emit_word(0xF002)?,
+            "plane" => {
+                let plane_token = self.next()?;
+                let mask = self.ranged(&plane_token, 0, 3, "a plane mask")?;
+                self.emit_word(0xF001 | (mask as u16) << 8)?;
+            },
+            "bcd" => self.register_op(0xF033)?,
+            "saveflags" => self.register_op(0xF075)?,
+            "loadflags" => self.register_op(0xF085)?,
+            "save" | "load" => {
+                let x = self.register()?;
+                if self.peek() == Some("-") {
+                    self.next()?;
+                    let y = self.register()?;
+                    let n = if token.text == "save" { 0x2 } else { 0x3 };
+                    self.emit_word(0x5000 | (x as u16) << 8 | (y as u16) << 4 | n)?;
+                } else {
+                    let nn = if token.text == "save" { 0x55 } else { 0x65 };
+                    self.emit_word(0xF000 | (x as u16) << 8 | nn)?;
+                }
+            },
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.emit_word(0xD000 | (x as u16) << 8 | (y as u16) << 4 | n as u16)?;
+            },
+            "jump" | "jump0" => {
+                let target = self.next()?;
+                let opcode = if token.text == "jump" { 0x1000 } else { 0xB000 };
+                self.address_operand(opcode, &target)?;
+            },
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let nn = match token.text.as_str() {
+                    "delay" => 0x15,
+                    "buzzer" => 0x18,
+                    _ => 0x3A,
+                };
+                self.register_op(0xF000 | nn)?;
+            },
+            "i" => self.index_statement()?,
+            "if" => self.if_statement(&token)?,
+            "else" => {
+                let (branch, _) = self.branches.pop().ok_or_else(|| error(&token, "'else' without 'if ... begin'"))?;
+                let skip_else = self.here;
+                self.emit_word(0x1000)?;
+                self.patch_jump(branch, self.here, &token)?;
+                self.branches.push((skip_else, token));
+            },
+            "end" => {
+                let (branch, _) = self.branches.pop().ok_or_else(|| error(&token, "'end' without 'if ... begin'"))?;
+                self.patch_jump(branch, self.here, &token)?;
+            },
+            "loop" => self.loops.push(Loop { start: self.here, token, exits: Vec::new() }),
+            "while" => {
+                if self.loops.is_empty() {
+                    return Err(error(&token, "'while' outside of a loop"));
+                }
+                self.condition(true)?;
+                let exit = self.here;
+                self.emit_word(0x1000)?;
+                self.loops.last_mut().unwrap().exits.push(exit);
+            },
+            "again" => {
+                let open = self.loops.pop().ok_or_else(|| error(&token, "'again' without 'loop'"))?;
+                let start = Self::jump_target(open.start, &open.token)?;
+                self.emit_word(0x1000 | start)?;
+                for exit in open.exits {
+                    self.patch_jump(exit, self.here, &token)?;
+                }
+            },
+            text if self.is_register(text) => {
+                self.pending.push(token);
+                self.register_statement()?;
+            },
+            text if self.macros.contains_key(text) => self.expand(&token)?,
+            text if number(text).is_some() || self.constants.contains_key(text) => {
+                self.pending.push(token);
+                let value = self.byte()?;
+                self.emit(value)?;
+            },
+            _ => {
+                //A bare name calls the subroutine at that label
+                self.address_operand(0x2000, &token)?;
+            },
+        }
+        Ok(())
+    }
+
+    /// Points the placeholder jump at `jump` to `target`, which `token` is responsible for.
+    fn patch_jump(&mut self, jump: usize, target: usize, token: &Token) -> Result<(), AssemblyError> {
+        let target = Self::jump_target(target, token)?;
+        let offset = jump - PC_INITIAL as usize;
+        self.rom[offset] = 0x10 | (target >> 8) as u8;
+        self.rom[offset + 1] = target as u8;
+        Ok(())
+    }
+
+    fn jump_target(target: usize, token: &Token) -> Result<u16, AssemblyError> {
+        if target > 0xFFF {
+            return Err(error(token, format!("jump target 0x{:X} is beyond 0xFFF", target)));
+        }
+        Ok(target as u16)
+    }
+
+    /// A known `value` of `token` as an unsigned number no larger than `max`.
+    fn unsigned(token: &Token, value: f64, max: u32, what: &str) -> Result<u16, AssemblyError> {
+        let value = value.floor() as i64;
+        if value < 0 || value > max as i64 {
+            let shown = if value < 0 { value.to_string() } else { format!("0x{:X}", value) };
+            return Err(error(token, format!("{} must be between 0x0 and 0x{:X}, got {}", what, max, shown)));
+        }
+        Ok(value as u16)
+    }
+
+    fn register_op(&mut self, opcode: u16) -> Result<(), AssemblyError> {
+        let x = self.register()?;
+        self.emit_word(opcode | (x as u16) << 8)
+    }
+
+    fn index_statement(&mut self) -> Result<(), AssemblyError> {
+        let operator = self.next()?;
+        match operator.text.as_str() {
+            "+=" => self.register_op(0xF01E),
+            ":=" => {
+                let operand = self.next()?;
+                match operand.text.as_str() {
+                    "hex" => self.register_op(0xF029),
+                    "bighex" => self.register_op(0xF030),
+                    "long" => {
+                        let target = self.next()?;
+                        self.emit_word(0xF000)?;
+                        let value = self.reference(&target, Patch::Word)?.unwrap_or(0.0);
+                        self.emit_word(Self::unsigned(&target, value, 0xFFFF, "a long address")?)
+                    },
+                    _ => self.address_operand(0xA000, &operand),
+                }
+            },
+            _ => Err(error(&operator, format!("expected ':=' or '+=' after 'i', found '{}'", operator.text))),
+        }
+    }
+
+    fn register_statement(&mut self) -> Result<(), AssemblyError> {
+        let x = self.register()? as u16;
+        let operator = self.next()?;
+        let operand = self.next()?;
+        let xy = |y: u8, n: u16| 0x8000 | x << 8 | (y as u16) << 4 | n;
+        if self.is_register(&operand.text) {
+            self.pending.push(operand);
+            let y = self.register()?;
+            let n = match operator.text.as_str() {
+                ":=" => 0x0,
+                "|=" => 0x1,
+                "&=" => 0x2,
+                "^=" => 0x3,
+                "+=" => 0x4,
+                "-=" => 0x5,
+                ">>=" => 0x6,
+                "=-" => 0x7,
+                "<<=" => 0xE,
+                other => return Err(error(&operator, format!("unknown register operator '{}'", other))),
+            };
+            return self.emit_word(xy(y, n));
+        }
+        match (operator.text.as_str(), operand.text.as_str()) {
+            (":=", "random") => {
+                let mask = self.byte()?;
+                self.emit_word(0xC000 | x << 8 | mask as u16)
+            },
+            (":=", "key") => self.emit_word(0xF00A | x << 8),
+            (":=", "delay") => self.emit_word(0xF007 | x << 8),
+            (":=", _) | ("+=", _) | ("-=", _) => {
+                self.pending.push(operand);
+                let value = self.byte()?;
+                match operator.text.as_str() {
+                    ":=" => self.emit_word(0x6000 | x << 8 | value as u16),
+                    "+=" => self.emit_word(0x7000 | x << 8 | value as u16),
+                    _ => self.emit_word(0x7000 | x << 8 | value.wrapping_neg() as u16),
+                }
+            },
+            _ => Err(error(&operand, format!("'{}' needs a register operand, found '{}'", operator.text, operand.text))),
+        }
+    }
+
+    /// `if` followed by a condition and `then`, which guards the next statement, or `begin`,
+    /// which opens a block closed by `end` with an optional `else`.
+    fn if_statement(&mut self, token: &Token) -> Result<(), AssemblyError> {
+        //Find out which form this is before compiling the condition
+        let form = self.pending.iter().rev().take(4)
+            .find(|t| t.text == "then" || t.text == "begin")
+            .map(|t| t.text.clone());
+        match form.as_deref() {
+            Some("then") => {
+                self.condition(false)?;
+                self.expect("then")?;
+            },
+            Some("begin") => {
+                self.condition(true)?;
+                self.expect("begin")?;
+                self.branches.push((self.here, token.clone()));
+                self.emit_word(0x1000)?;
+            },
+            _ => return Err(error(token, "expected 'then' or 'begin' after the condition")),
+        }
+        Ok(())
+    }
+
+    /// Emits code that skips the next instruction when the condition does not hold, or when it
+    /// does if `negated`. `<`, `>`, `<=` and `>=` compare through `compare-temp`, VF by default.
+    fn condition(&mut self, negated: bool) -> Result<(), AssemblyError> {
+        let x = self.register()? as u16;
+        let comparison = self.next()?;
+        let mut operator = comparison.text.clone();
+        if negated {
+            operator = match operator.as_str() {
+                "==" => "!=",
+                "!=" => "==",
+                "key" => "-key",
+                "-key" => "key",
+                "<" => ">=",
+                ">" => "<=",
+                "<=" => ">",
+                ">=" => "<",
+                other => return Err(error(&comparison, format!("unknown comparison '{}'", other))),
+            }.to_string();
+        }
+        match operator.as_str() {
+            "key" => return self.emit_word(0xE0A1 | x << 8),
+            "-key" => return self.emit_word(0xE09E | x << 8),
+            _ => (),
+        }
+        let operand = self.next()?;
+        let register = if self.is_register(&operand.text) {
+            self.pending.push(operand.clone());
+            Some(self.register()? as u16)
+        } else {
+            None
+        };
+        let value = match register {
+            Some(_) => 0,
+            None => {
+                self.pending.push(operand.clone());
+                self.byte()? as u16
+            },
+        };
+        let temp = self.aliases.get("compare-temp").copied().unwrap_or(0xF) as u16;
+        match (operator.as_str(), register) {
+            ("==", Some(y)) => self.emit_word(0x9000 | x << 8 | y << 4),
+            ("==", None) => self.emit_word(0x4000 | x << 8 | value),
+            ("!=", Some(y)) => self.emit_word(0x5000 | x << 8 | y << 4),
+            ("!=", None) => self.emit_word(0x3000 | x << 8 | value),
+            (">", _) | ("<", _) | (">=", _) | ("<=", _) => {
+                match register {
+                    Some(y) => self.emit_word(0x8000 | temp << 8 | y << 4)?,
+                    None => self.emit_word(0x6000 | temp << 8 | value)?,
+                }
+                let subtract = if operator == ">" || operator == "<=" { 0x5 } else { 0x7 };
+                self.emit_word(0x8000 | temp << 8 | x << 4 | subtract)?;
+                let skip = if operator == ">" || operator == "<" { 0x3F01 } else { 0x4F01 };
+                self.emit_word(skip)
+            },
+            (other, _) => Err(error(&comparison, format!("unknown comparison '{}'", other))),
+        }
+    }
+
+    fn expand(&mut self, token: &Token) -> Result<(), AssemblyError> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(error(token, format!("too many macro expansions; does '{}' invoke itself?", token.text)));
+        }
+        let (parameters, body) = {
+            let definition = &self.macros[&token.text];
+            (definition.parameters.clone(), definition.body.clone())
+        };
+        let mut arguments = HashMap::new();
+        for parameter in parameters {
+            let argument = self.next()?;
+            arguments.insert(parameter, argument.text);
+        }
+        for body_token in body.into_iter().rev() {
+            let text = arguments.get(&body_token.text).cloned().unwrap_or(body_token.text);
+            self.pending.push(Token { text, ..body_token });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assemble_error(source: &str) -> AssemblyError {
+        assemble(source).expect_err("source should not assemble")
+    }
+
+    #[test]
+    fn structured_jumps_resolve() {
+        let rom = assemble("loop v0 += 1 while v0 != 4 again").unwrap();
+        assert_eq!(rom, [0x70, 0x01, 0x40, 0x04, 0x12, 0x08, 0x12, 0x00]);
+    }
+
+    #[test]
+    fn main_is_reached_by_a_leading_jump() {
+        let rom = assemble(": data 0xAB : main jump main").unwrap();
+        assert_eq!(rom, [0x12, 0x03, 0xAB, 0x12, 0x03]);
+    }
+
+    #[test]
+    fn shifts_out_of_range_are_errors() {
+        let error = assemble_error(":calc x { 1 << 70 }");
+        assert_eq!((error.line, error.column), (1, 13));
+        assert!(error.message.contains("invalid shift"));
+        assert!(assemble_error(":calc x { 1 >> -1 }").message.contains("invalid shift"));
+        assert_eq!(assemble(":calc x { 1 << 4 } :byte x").unwrap(), [0x10]);
+    }
+
+    #[test]
+    fn jump_targets_beyond_12_bits_are_errors() {
+        assert!(assemble_error(":org 0x1000 loop again").message.contains("0x1000"));
+        assert!(assemble_error(":org 0xFFE loop while v0 == 1 again").message.contains("0x1004"));
+        assert!(assemble_error(":org 0xFFC if v0 == 1 begin v1 := 2 end").message.contains("0x1002"));
+        assert!(assemble_error("jump 0x1000").message.contains("0x1000"));
+        assert!(assemble_error("jump later :org 0x1000 : later").message.contains("0x1000"));
+    }
+
+    #[test]
+    fn wide_operands_are_range_checked() {
+        assert!(assemble_error(":unpack 1 0x1234").message.contains("0x1234"));
+        assert!(assemble_error("i := long 0x12345").message.contains("0x12345"));
+        assert!(assemble_error(":pointer -1").message.contains("-1"));
+        assert_eq!(assemble(":unpack 1 0x234").unwrap(), [0x60, 0x12, 0x61, 0x34]);
+        assert_eq!(assemble("i := long 0x1234").unwrap(), [0xF0, 0x00, 0x12, 0x34]);
+    }
+
+    #[test]
+    fn errors_report_the_offending_token() {
+        let error = assemble_error("v0 := 1\n  jump nowhere");
+        assert_eq!((error.line, error.column), (2, 8));
+        assert_eq!(error.to_string(), "line 2, column 8: undefined name 'nowhere'");
+    }
+}
@@ -0,0 +1,123 @@
+//! The expression language of `:calc` and `:byte { ... }`. As in Octo, operators have no
+//! precedence and evaluate from right to left, so `2 * 3 + 1` is 8; parentheses group.
+
+use std::convert::TryFrom;
+
+use super::Token;
+
+/// Names an expression may refer to, resolved by the assembler.
+pub trait Scope {
+    /// The value of a label, constant or built-in such as `HERE`.
+    fn lookup(&self, name: &str) -> Option<f64>;
+    /// The byte already assembled at `address`, for `@`.
+    fn peek(&self, address: f64) -> Option<f64>;
+}
+
+/// Evaluates the tokens of an expression, without its surrounding braces.
+pub fn evaluate(tokens: &[Token], scope: &dyn Scope) -> Result<f64, (Token, String)> {
+    let mut parser = Parser { tokens, position: 0, scope };
+    let value = parser.expression()?;
+    match tokens.get(parser.position) {
+        Some(token) => Err((token.clone(), format!("unexpected '{}' in expression", token.text))),
+        None => Ok(value),
+    }
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    scope: &'a dyn Scope,
+}
+
+impl<'a> Parser<'a> {
+    fn next(&mut self) -> Result<&'a Token, (Token, String)> {
+        match self.tokens.get(self.position) {
+            Some(token) => {
+                self.position += 1;
+                Ok(token)
+            },
+            None => {
+                let last = self.tokens.last().cloned().unwrap_or(Token { text: String::new(), line: 0, column: 0 });
+                Err((last, String::from("expression ends too early")))
+            },
+        }
+    }
+
+    fn expression(&mut self) -> Result<f64, (Token, String)> {
+        let left = self.term()?;
+        let operator = match self.tokens.get(self.position) {
+            Some(token) if token.text != ")" => token,
+            _ => return Ok(left),
+        };
+        self.position += 1;
+        let right = self.expression()?;
+        let value = match operator.text.as_str() {
+            "+" => left + right,
+            "-" => left - right,
+            "*" => left * right,
+            "/" => left / right,
+            "%" => left % right,
+            "&" => (left as i64 & right as i64) as f64,
+            "|" => (left as i64 | right as i64) as f64,
+            "^" => (left as i64 ^ right as i64) as f64,
+            "<<" | ">>" => {
+                //Shifting an i64 by 64 or more, or by a negative count, is not defined
+                let count = match u32::try_from(right as i64) {
+                    Ok(count) if count < 64 => count,
+                    _ => return Err((operator.clone(), format!("invalid shift by {}", right))),
+                };
+                if operator.text == "<<" {
+                    ((left as i64) << count) as f64
+                } else {
+                    ((left as i64) >> count) as f64
+                }
+            },
+            "pow" => left.powf(right),
+            "min" => left.min(right),
+            "max" => left.max(right),
+            "<" => (left < right) as i64 as f64,
+            ">" => (left > right) as i64 as f64,
+            "<=" => (left <= right) as i64 as f64,
+            ">=" => (left >= right) as i64 as f64,
+            "==" => (left == right) as i64 as f64,
+            "!=" => (left != right) as i64 as f64,
+            other => return Err((operator.clone(), format!("unknown operator '{}'", other))),
+        };
+        Ok(value)
+    }
+
+    fn term(&mut self) -> Result<f64, (Token, String)> {
+        let token = self.next()?;
+        let unary = |f: fn(f64) -> f64, parser: &mut Self| parser.term().map(f);
+        match token.text.as_str() {
+            "(" => {
+                let value = self.expression()?;
+                match self.next()? {
+                    close if close.text == ")" => Ok(value),
+                    other => Err((other.clone(), String::from("expected ')'"))),
+                }
+            },
+            "-" => unary(|x| -x, self),
+            "~" => unary(|x| !(x as i64) as f64, self),
+            "!" => unary(|x| (x == 0.0) as i64 as f64, self),
+            "sin" => unary(f64::sin, self),
+            "cos" => unary(f64::cos, self),
+            "tan" => unary(f64::tan, self),
+            "exp" => unary(f64::exp, self),
+            "log" => unary(f64::ln, self),
+            "abs" => unary(f64::abs, self),
+            "sqrt" => unary(f64::sqrt, self),
+            "sign" => unary(f64::signum, self),
+            "ceil" => unary(f64::ceil, self),
+            "floor" => unary(f64::floor, self),
+            "@" => {
+                let address = self.term()?;
+                self.scope.peek(address)
+                    .ok_or_else(|| (token.clone(), format!("nothing has been assembled at {}", address)))
+            },
+            text => super::number(text)
+                .or_else(|| self.scope.lookup(text))
+                .ok_or_else(|| (token.clone(), format!("undefined name '{}'", text))),
+        }
+    }
+}
@@ -0,0 +1,33 @@
+/// A whitespace-separated word of source, with the 1-based position of its first character.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub text: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Splits Octo source into tokens. Comments run from `#` to the end of the line, and braces and
+/// parentheses are tokens of their own even without surrounding whitespace.
+pub fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for (number, line) in source.lines().enumerate() {
+        let mut current: Option<Token> = None;
+        for (index, c) in line.chars().enumerate() {
+            if c == '#' {
+                break;
+            }
+            if c.is_whitespace() || "{}()".contains(c) {
+                tokens.extend(current.take());
+                if !c.is_whitespace() {
+                    tokens.push(Token { text: c.to_string(), line: number + 1, column: index + 1 });
+                }
+                continue;
+            }
+            current.get_or_insert_with(|| Token { text: String::new(), line: number + 1, column: index + 1 })
+                .text
+                .push(c);
+        }
+        tokens.extend(current);
+    }
+    tokens
+}
@@ -1,307 +1,715 @@
-use rand::Rng;
+pub mod constants;
+mod bus;
+mod cache;
+mod config;
+mod debug;
+mod display;
+mod error;
+mod font;
+mod instruction;
+mod quirks;
+mod random;
+mod rewind;
+mod scheduler;
+mod state;
+pub mod timing;
 
-mod constants;
+pub use bus::{Bus, BusHook, HookId};
+use cache::DecodeCache;
+pub use config::{Config, Platform};
+pub use debug::RunTarget;
+pub use display::Display;
+pub use error::{Chip8Error, StateError};
+pub use font::{FontSet, FONT_SIZE};
+pub use instruction::{DecodeError, Instruction};
+pub use quirks::{Quirks, QuirksPreset};
+pub use random::{RandomSource, XorShift, DEFAULT_SEED};
+pub use rewind::RewindBuffer;
+pub use scheduler::{FrameScheduler, FRAME_RATE};
+pub use state::{MAGIC as STATE_MAGIC, VERSION as STATE_VERSION};
+pub use timing::TimingMode;
 
+/// A CHIP-8 interpreter. The core has no knowledge of windows, audio or input devices; the
+/// host feeds it the keypad state on every `tick` and reads the framebuffer back out.
 pub struct Chip8 {
-    mem: [u8; constants::MEMORY_SIZE],
+    bus: Bus,
     regs: [u8; constants::NUM_REGISTERS],
     pc: u16,
     idx: u16,
-    stack: [u16; 16],
-    sp: i8,
-    pub sound: u8,
+    stack: Vec<u16>,
+    /// Number of return addresses on the stack.
+    sp: usize,
+    sound: u8,
     delay: u8,
     keyboard: [bool; 16],
-    pub screen: [bool; (constants::SCREEN_WIDTH * constants::SCREEN_HEIGHT) as usize],
+    screen: Display,
+    rpl: [u8; constants::NUM_RPL_FLAGS],
+    planes: u8,
+    audio_pattern: [u8; constants::AUDIO_PATTERN_SIZE],
+    pitch: u8,
+    rom: Vec<u8>,
+    config: Config,
+    op_address: u16,
+    cache: Option<DecodeCache>,
+    rng: Box<dyn RandomSource>,
+    /// The status `resume` returns to while `status` is `Paused`.
+    resume_status: CpuStatus,
     pub redraw: bool,
     pub status: CpuStatus,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CpuStatus {
     Running,
-    AwaitingKeyPress,
+    /// FX0A is waiting for a key to go down; the key will be written to VX.
+    AwaitingKeyPress { x: u8 },
+    /// FX0A saw `key` go down and resumes once it is released, as the COSMAC VIP did.
+    AwaitingKeyRelease { x: u8, key: u8 },
+    /// The host paused execution; neither instructions nor timers advance until `resume`.
+    Paused,
+    /// A `Chip8Error` stopped execution; only `reset` resumes the interpreter.
+    Halted,
+    /// The program ran the SUPER-CHIP/XO-CHIP 00FD exit instruction.
+    Exited,
+}
+
+impl CpuStatus {
+    /// Blocked in FX0A. The timers keep counting down and `tick` only polls the keypad.
+    pub fn is_waiting_for_key(&self) -> bool {
+        matches!(self, CpuStatus::AwaitingKeyPress { .. } | CpuStatus::AwaitingKeyRelease { .. })
+    }
 }
 
 impl Chip8 {
-    pub fn new(rom_path: String) -> Self {
+    /// Builds an interpreter with the font and `rom` loaded at their configured addresses.
+    pub fn new(rom: &[u8], config: Config) -> Result<Self, Chip8Error> {
+        let font_end = config.font_address as usize + FONT_SIZE + if config.platform == Platform::Chip8 {
+            0
+        } else {
+            constants::BIG_FONT_DATA.len()
+        };
+        if config.memory_size > constants::MAX_MEMORY_SIZE {
+            return Err(Chip8Error::InvalidConfig("memory is larger than the 64 KiB address space"));
+        }
+        if config.memory_size < font_end {
+            return Err(Chip8Error::InvalidConfig("memory is too small to hold the font"));
+        }
+        if config.load_address as usize >= config.memory_size {
+            return Err(Chip8Error::InvalidConfig("load address is outside memory"));
+        }
+        if config.stack_depth == 0 {
+            return Err(Chip8Error::InvalidConfig("stack depth must be at least 1"));
+        }
+        let capacity = config.memory_size - config.load_address as usize;
+        if rom.len() > capacity {
+            return Err(Chip8Error::RomTooLarge { size: rom.len(), capacity });
+        }
         let mut cpu = Self {
-            mem: [0; constants::MEMORY_SIZE],
+            bus: Bus::new(0),
             regs: [0; constants::NUM_REGISTERS],
-            pc: constants::PC_INITIAL,
+            pc: config.load_address,
             idx: 0,
-            stack: [0; 16],
-            sp: -1,
+            stack: Vec::new(),
+            sp: 0,
             sound: 0,
             delay: 0,
             keyboard: [false; 16],
-            screen: [false; (constants::SCREEN_WIDTH * constants::SCREEN_HEIGHT) as usize],
+            screen: Display::new(),
+            rpl: [0; constants::NUM_RPL_FLAGS],
+            planes: 1,
+            audio_pattern: [0; constants::AUDIO_PATTERN_SIZE],
+            pitch: 64,
+            rom: rom.to_vec(),
+            config,
+            op_address: config.load_address,
+            cache: None,
+            rng: Box::new(XorShift::new(config.seed)),
+            resume_status: CpuStatus::Running,
             redraw: false,
             status: CpuStatus::Running,
         };
-        for number in 0..80 {
-            cpu.mem[number] = constants::FONT_DATA[number];
+        cpu.reset();
+        Ok(cpu)
+    }
+
+    /// Replaces the generator CXNN draws from, e.g. with one shared by a test harness.
+    pub fn set_rng(&mut self, rng: Box<dyn RandomSource>) {
+        self.rng = rng;
+    }
+
+    pub fn rng_state(&self) -> u64 {
+        self.rng.state()
+    }
+
+    pub fn set_rng_state(&mut self, state: u64) {
+        self.rng.restore(state);
+    }
+
+    /// Restores the power-on state and reloads the ROM the interpreter was built with. The random
+    /// number generator is left alone; use `set_rng_state` to replay from a known point.
+    pub fn reset(&mut self) {
+        *self.bus.memory_mut() = vec![0; self.config.memory_size];
+        self.cache = if self.config.decode_cache { Some(DecodeCache::new(self.bus.len())) } else { None };
+        self.regs = [0; constants::NUM_REGISTERS];
+        self.pc = self.config.load_address;
+        self.op_address = self.config.load_address;
+        self.idx = 0;
+        self.stack = vec![0; self.config.stack_depth];
+        self.sp = 0;
+        self.sound = 0;
+        self.delay = 0;
+        self.keyboard = [false; 16];
+        self.screen = Display::new();
+        self.rpl = [0; constants::NUM_RPL_FLAGS];
+        self.planes = 1;
+        self.audio_pattern = [0; constants::AUDIO_PATTERN_SIZE];
+        self.pitch = 64;
+        self.redraw = true;
+        self.status = CpuStatus::Running;
+        let font_address = self.config.font_address as usize;
+        let font = self.config.font;
+        let mem = self.bus.memory_mut();
+        mem[font_address..font_address + FONT_SIZE].copy_from_slice(font.data());
+        if self.config.platform != Platform::Chip8 {
+            let big_font_address = font_address + FONT_SIZE;
+            mem[big_font_address..big_font_address + constants::BIG_FONT_DATA.len()].copy_from_slice(&constants::BIG_FONT_DATA);
         }
-        let rom = std::fs::read(rom_path).unwrap();
-        for (pos, e) in rom.iter().enumerate() {
-            cpu.mem[cpu.pc as usize + pos] = *e;
+        for (pos, e) in self.rom.iter().enumerate() {
+            mem[self.config.load_address as usize + pos] = *e;
         }
-        return cpu;
     }
 
-    pub fn decrement_counters(&mut self) {
-        if self.delay > 0 { self.delay = self.delay - 1 }
-        if self.sound > 0 { self.sound = self.sound - 1 }
+    pub fn screen(&self) -> &Display {
+        &self.screen
     }
 
-    pub fn tick(&mut self, kb_state: [bool; 16]) {
-        self.keyboard = kb_state;
-        //fetch
-        let instruction = Self::fetch(self);
-        //decode
-        let (category, x, y, n, nn, nnn) = Self::decode(self, instruction);
-        //execute
-        match category {
-            0x0 => Self::category_0(self, n),
-            0x1 => Self::category_1(self, nnn),
-            0x2 => Self::category_2(self, nnn),
-            0x3 => Self::category_3(self, x, nn),
-            0x4 => Self::category_4(self, x, nn),
-            0x5 => Self::category_5(self, x, y),
-            0x6 => Self::category_6(self, x, nn),
-            0x7 => Self::category_7(self, x, nn),
-            0x8 => Self::category_8(self, x, y, n),
-            0x9 => Self::category_9(self, x, y),
-            0xA => Self::category_a(self, nnn),
-            0xB => Self::category_b(self, nnn),
-            0xC => Self::category_c(self, x, nn),
-            0xD => Self::category_d(self, x, y, n),
-            0xE => Self::category_e(self, x, n),
-            0xF => Self::category_f(self, x, nn),
-            _ => {
-                panic!("Unknown opcode, panicking.");
-            }
-        }
+    pub fn memory(&self) -> &[u8] {
+        self.bus.memory()
     }
 
-    fn fetch(&mut self) -> u16 {
-        let mut opcode: u16 = 0;
-        opcode = opcode + (self.mem[self.pc as usize]) as u16;
-        opcode = opcode << 8;
-        opcode = opcode + (self.mem[(self.pc+1) as usize]) as u16;
-        self.pc = self.pc + 2;
-        return opcode;
+    pub fn bus(&self) -> &Bus {
+        &self.bus
     }
 
-    fn decode(&self, instruction: u16) -> (u8, u8, u8, u8, u8, u16) {
-        let category: u8 = (instruction >> 12) as u8;
-        let x: u8 = ((instruction & 0x0F00) >> 8) as u8;
-        let y: u8 = ((instruction & 0x00F0) >> 4) as u8;
-        let n: u8 = (instruction & 0x000F) as u8;
-        let nn: u8 = (instruction & 0x00FF) as u8;
-        let nnn: u16 = instruction & 0x0FFF;
-        return (category, x, y, n, nn, nnn);
+    /// Attaches a hook to every access within `range`. Hooks stay attached across `reset` and
+    /// `load_state`.
+    pub fn add_hook(&mut self, range: std::ops::Range<usize>, hook: Box<dyn BusHook>) -> HookId {
+        self.bus.add_hook(range, hook)
     }
 
-    fn category_0(&mut self, n: u8) {
-        match n {
-            0x0 => self.screen = [false; (constants::SCREEN_WIDTH * constants::SCREEN_HEIGHT) as usize],
-            0xE => {
-                self.pc = self.stack[self.sp as usize];
-                self.sp = self.sp - 1;
-            },
-            _ => panic!("Unsupported opcode.")
-        }
+    pub fn remove_hook(&mut self, id: HookId) -> Option<Box<dyn BusHook>> {
+        self.bus.remove_hook(id)
     }
 
-    fn category_1(&mut self, nnn: u16) {
-        self.pc = nnn;
+    pub fn registers(&self) -> &[u8; constants::NUM_REGISTERS] {
+        &self.regs
     }
 
-    fn category_2(&mut self, nnn: u16) {
-        self.sp = self.sp + 1;
-        self.stack[self.sp as usize] = self.pc;
-        self.pc = nnn;
+    pub fn pc(&self) -> u16 {
+        self.pc
     }
 
-    fn category_3(&mut self, x: u8, nn: u8) {
-        let xval: u8 = self.regs[x as usize];
-        if xval == nn {
-            self.pc = self.pc + 2;
-        }
+    pub fn index(&self) -> u16 {
+        self.idx
     }
 
-    fn category_4(&mut self, x: u8, nn: u8) {
-        let xval: u8 = self.regs[x as usize];
-        if xval != nn {
-            self.pc = self.pc + 2;
-        }
+    /// The active portion of the call stack, oldest return address first.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.sp]
     }
 
-    fn category_5(&mut self, x: u8, y: u8) {
-        let xval: u8 = self.regs[x as usize];
-        let yval: u8 = self.regs[y as usize];
-        if xval == yval {
-            self.pc = self.pc + 2;
-        }
+    pub fn config(&self) -> &Config {
+        &self.config
     }
 
-    fn category_6(&mut self, x: u8, nn: u8) {
-        self.regs[x as usize] = nn;
+    /// The RPL user flags written by FX75. SUPER-CHIP only uses the first eight.
+    pub fn rpl_flags(&self) -> &[u8; constants::NUM_RPL_FLAGS] {
+        &self.rpl
     }
 
-    fn category_7(&mut self, x: u8, nn: u8) {
-        let addn: (u8, bool) = self.regs[x as usize].overflowing_add(nn);
-        self.regs[x as usize] = addn.0;
+    /// Bitmask of the XO-CHIP planes that drawing, clearing and scrolling currently affect.
+    pub fn selected_planes(&self) -> u8 {
+        self.planes
     }
 
-    fn category_8(&mut self, x: u8, y: u8, n: u8) {
-        match n {
-            0x0 => self.regs[x as usize] = self.regs[y as usize],
-            0x1 => self.regs[x as usize] = self.regs[x as usize] | self.regs[y as usize],
-            0x2 => self.regs[x as usize] = self.regs[x as usize] & self.regs[y as usize],
-            0x3 => self.regs[x as usize] = self.regs[x as usize] ^ self.regs[y as usize],
-            0x4 => {
-                let addn: (u8, bool) = self.regs[x as usize].overflowing_add(self.regs[y as usize]);
-                self.regs[x as usize] = addn.0;
-                if addn.1 {
-                    self.regs[0xF] = 1;
-                } else {
-                    self.regs[0xF] = 0;
+    /// The XO-CHIP 1-bit audio pattern loaded by F002, and the playback pitch set by FX3A.
+    pub fn audio(&self) -> (&[u8; constants::AUDIO_PATTERN_SIZE], u8) {
+        (&self.audio_pattern, self.pitch)
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound
+    }
+
+    pub fn decrement_counters(&mut self) {
+        if self.delay > 0 { self.delay -= 1 }
+        if self.sound > 0 { self.sound -= 1 }
+    }
+
+    /// Executes a single instruction with `kb_state` as the current keypad state. On error the
+    /// interpreter is left `Halted` with the program counter past the faulting instruction.
+    /// Ticking a paused, halted or exited interpreter does nothing.
+    pub fn tick(&mut self, kb_state: [bool; 16]) -> Result<(), Chip8Error> {
+        let previous = self.keyboard;
+        self.keyboard = kb_state;
+        match self.status {
+            CpuStatus::AwaitingKeyPress { x } => {
+                //Only a fresh press counts, so a key still held from before FX0A is ignored
+                if let Some(key) = (0..16).find(|&k| kb_state[k] && !previous[k]) {
+                    self.status = CpuStatus::AwaitingKeyRelease { x, key: key as u8 };
                 }
+                return Ok(());
             },
-            0x5 => {
-                let xval = self.regs[x as usize];
-                let yval = self.regs[y as usize];
-                if xval > yval {
-                    self.regs[0xF] = 1;
-                    self.regs[x as usize] = xval - yval;
-                } else {
-                    self.regs[0xF] = 0;
-                    self.regs[x as usize] = xval.wrapping_sub(yval);
+            CpuStatus::AwaitingKeyRelease { x, key } => {
+                if !kb_state[key as usize] {
+                    self.regs[x as usize] = key;
+                    self.status = CpuStatus::Running;
                 }
+                return Ok(());
             },
-            0x6 => {
-                self.regs[0xF] = (self.regs[x as usize] & 0x1) as u8;
-                self.regs[x as usize] = self.regs[x as usize] >> 1;
+            //A fault or 00FD stops the program for good; only `reset` starts it again
+            CpuStatus::Paused | CpuStatus::Halted | CpuStatus::Exited => return Ok(()),
+            CpuStatus::Running => (),
+        }
+        let result = Self::step(self);
+        if result.is_err() {
+            self.status = CpuStatus::Halted;
+        }
+        result
+    }
+
+    /// Pauses a running or key-waiting interpreter. Halted and exited interpreters stay as they are.
+    pub fn pause(&mut self) {
+        if self.status == CpuStatus::Running || self.status.is_waiting_for_key() {
+            self.resume_status = self.status;
+            self.status = CpuStatus::Paused;
+        }
+    }
+
+    pub fn resume(&mut self) {
+        if self.status == CpuStatus::Paused {
+            self.status = self.resume_status;
+        }
+    }
+
+    /// Decodes the instruction at `pc` without executing it.
+    pub fn current_instruction(&self) -> Result<Instruction, DecodeError> {
+        Instruction::read(self.bus.memory(), self.pc as usize)
+    }
+
+    fn step(&mut self) -> Result<(), Chip8Error> {
+        let instruction = Self::fetch(self)?;
+        Self::execute(self, instruction)
+    }
+
+    /// Fetches and decodes the instruction at `pc`, leaving `pc` pointing past it. Instructions
+    /// from a later platform than the configured one are reported as unknown opcodes.
+    fn fetch(&mut self) -> Result<Instruction, Chip8Error> {
+        self.op_address = self.pc;
+        self.bus.execute(self.pc);
+        if let Some(cache) = &self.cache {
+            if let Some(instruction) = cache.get(self.pc as usize) {
+                self.pc = self.pc.wrapping_add(instruction.size());
+                return Ok(instruction);
+            }
+        }
+        let word = Self::fetch_word(self, self.pc as usize)?;
+        self.pc = self.pc.wrapping_add(2);
+        let decoded = match Instruction::decode(word) {
+            Err(DecodeError::NeedsOperand(_)) => {
+                let operand = Self::fetch_word(self, self.pc as usize)?;
+                self.pc = self.pc.wrapping_add(2);
+                Instruction::decode_long(word, operand)
             },
-            0x7 => {
-                let xval = self.regs[x as usize];
-                let yval = self.regs[y as usize];
-                if yval > xval {
-                    self.regs[0xF] = 1;
-                    self.regs[x as usize] = yval - xval;
-                } else {
-                    self.regs[0xF] = 0;
-                    self.regs[x as usize] = yval.wrapping_sub(xval);
+            other => other,
+        };
+        match decoded {
+            Ok(instruction) if instruction.platform() <= self.config.platform => {
+                if let Some(cache) = &mut self.cache {
+                    cache.insert(self.op_address as usize, instruction);
                 }
+                Ok(instruction)
             },
-            0xE => {
-                self.regs[0xF] = ((self.regs[x as usize] & 0xA0) >> 7) as u8;
-                self.regs[x as usize] = self.regs[x as usize] << 1;
-            },
-            _ => panic!("Unsupported op code")
+            _ => Err(Chip8Error::UnknownOpcode { address: self.op_address, opcode: word }),
         }
     }
 
-    fn category_9(&mut self, x: u8, y: u8) {
-        let xval: u8 = self.regs[x as usize];
-        let yval: u8 = self.regs[y as usize];
-        if xval != yval {
-            self.pc = self.pc + 2;
+    /// Reads an instruction word. Fetches bypass the read hooks; see `BusHook`.
+    fn fetch_word(&self, target: usize) -> Result<u16, Chip8Error> {
+        match self.bus.memory().get(target..target + 2) {
+            Some(word) => Ok(((word[0] as u16) << 8) | word[1] as u16),
+            None => Err(Chip8Error::MemoryOutOfBounds { address: self.op_address, target: target.max(self.bus.len()) }),
         }
     }
 
-    fn category_a(&mut self, nnn: u16) {
-        self.idx = nnn;
+    fn read_word(&mut self, target: usize) -> Result<u16, Chip8Error> {
+        let high = Self::read(self, target)? as u16;
+        let low = Self::read(self, target + 1)? as u16;
+        Ok((high << 8) | low)
+    }
+
+    fn read(&mut self, target: usize) -> Result<u8, Chip8Error> {
+        match self.bus.read(target) {
+            Some(value) => Ok(value),
+            None => Err(Chip8Error::MemoryOutOfBounds { address: self.op_address, target }),
+        }
     }
 
-    fn category_b(&mut self, nnn: u16) {
-        self.pc = (self.regs[0] as u16) + nnn;
+    fn write(&mut self, target: usize, value: u8) -> Result<(), Chip8Error> {
+        if !self.bus.write(target, value) {
+            return Err(Chip8Error::MemoryOutOfBounds { address: self.op_address, target });
+        }
+        if let Some(cache) = &mut self.cache {
+            cache.invalidate(target);
+        }
+        Ok(())
     }
 
-    fn category_c(&mut self, x: u8, nn: u8) {
-        let rnum: u8 = rand::thread_rng().gen_range(0, 256) as u8;
-        self.regs[x as usize] = rnum & nn;
+    /// Skips the next instruction, which on XO-CHIP may be the four byte `F000 NNNN`.
+    fn skip(&mut self) {
+        let long = self.config.platform == Platform::XoChip
+            && self.bus.memory().get(self.pc as usize) == Some(&0xF0)
+            && self.bus.memory().get(self.pc as usize + 1) == Some(&0x00);
+        self.pc = self.pc.wrapping_add(if long { 4 } else { 2 });
     }
 
-    fn category_d(&mut self, x: u8, y: u8, n: u8) {
-        let xcoord: usize = (self.regs[x as usize] as usize) % (constants::SCREEN_WIDTH as usize);
-        let ycoord: usize = (self.regs[y as usize] as usize) % (constants::SCREEN_HEIGHT as usize);
-        self.regs[0xF] = 0;
-        for number in 0..(n as usize) {
-            if ycoord + number >= constants::SCREEN_HEIGHT as usize {
-                continue;
-            }
-            let line = self.mem[(self.idx as usize) + number];
-            for x in 0..8 {
-                let pix_idx = xcoord + x + ((ycoord + number)* constants::SCREEN_WIDTH as usize);
-                if (xcoord + x) >= constants::SCREEN_WIDTH as usize {
-                    continue;
-                }
-                let sprite_pixel = ((line as usize) >> (7 - x)) & 0x1;
-                let cur_pixel = self.screen[pix_idx];
-                let new_pixel = cur_pixel ^ (sprite_pixel != 0);
-                if cur_pixel == true && new_pixel == false {
-                    self.regs[0xF] = 1;
-                }
-                self.screen[pix_idx] = new_pixel;
-            }
+    fn skip_if(&mut self, condition: bool) {
+        if condition {
+            Self::skip(self);
         }
-        self.redraw = true;
     }
 
-    fn category_e(&mut self, x: u8, n: u8) {
-        let xval = self.regs[x as usize];
-        match n {
-            0x1 => {
-                if !self.keyboard[xval as usize] {
-                    self.pc = self.pc + 2;
+    fn execute(&mut self, instruction: Instruction) -> Result<(), Chip8Error> {
+        let planes = self.planes;
+        match instruction {
+            Instruction::ScrollDown(rows) => {
+                self.screen.scroll_down(rows as usize, planes);
+                self.redraw = true;
+            },
+            Instruction::ScrollUp(rows) => {
+                self.screen.scroll_up(rows as usize, planes);
+                self.redraw = true;
+            },
+            Instruction::Cls => {
+                self.screen.clear(planes);
+                self.redraw = true;
+            },
+            Instruction::Ret => {
+                if self.sp == 0 {
+                    return Err(Chip8Error::StackUnderflow { address: self.op_address });
                 }
+                self.sp -= 1;
+                self.pc = self.stack[self.sp];
+            },
+            Instruction::ScrollRight => {
+                self.screen.scroll_right(4, planes);
+                self.redraw = true;
             },
-            0xE => {
-                if self.keyboard[xval as usize] {
-                    self.pc = self.pc + 2;
+            Instruction::ScrollLeft => {
+                self.screen.scroll_left(4, planes);
+                self.redraw = true;
+            },
+            Instruction::Exit => self.status = CpuStatus::Exited,
+            Instruction::Lores => {
+                self.screen.set_hires(false);
+                self.redraw = true;
+            },
+            Instruction::Hires => {
+                self.screen.set_hires(true);
+                self.redraw = true;
+            },
+            Instruction::Jp(addr) => self.pc = addr,
+            Instruction::Call(addr) => {
+                if self.sp >= self.stack.len() {
+                    return Err(Chip8Error::StackOverflow { address: self.op_address });
                 }
+                self.stack[self.sp] = self.pc;
+                self.sp += 1;
+                self.pc = addr;
             },
-            _ => panic!("Unsupported opcode")
-        }
-    }
-
-    fn category_f(&mut self, x: u8, nn: u8) {
-        match nn {
-            0x07 => self.regs[x as usize] = self.delay,
-            0x15 => self.delay = self.regs[x as usize],
-            0x18 => {
-                self.sound = self.regs[x as usize];
+            Instruction::SeByte { x, byte } => Self::skip_if(self, self.regs[x as usize] == byte),
+            Instruction::SneByte { x, byte } => Self::skip_if(self, self.regs[x as usize] != byte),
+            Instruction::SeReg { x, y } => Self::skip_if(self, self.regs[x as usize] == self.regs[y as usize]),
+            Instruction::SneReg { x, y } => Self::skip_if(self, self.regs[x as usize] != self.regs[y as usize]),
+            //XO-CHIP 5XY2/5XY3 save and load VX..VY, in descending order if X > Y, without touching I
+            Instruction::SaveRange { x, y } => {
+                for (offset, reg) in Self::register_range(x, y).enumerate() {
+                    Self::write(self, self.idx as usize + offset, self.regs[reg])?;
+                }
+            },
+            Instruction::LoadRange { x, y } => {
+                for (offset, reg) in Self::register_range(x, y).enumerate() {
+                    self.regs[reg] = Self::read(self, self.idx as usize + offset)?;
+                }
+            },
+            Instruction::LdByte { x, byte } => self.regs[x as usize] = byte,
+            Instruction::AddByte { x, byte } => self.regs[x as usize] = self.regs[x as usize].wrapping_add(byte),
+            Instruction::LdReg { x, y } => self.regs[x as usize] = self.regs[y as usize],
+            Instruction::Or { x, y } => {
+                self.regs[x as usize] |= self.regs[y as usize];
+                if self.config.quirks.logic_resets_vf { self.regs[0xF] = 0 }
             },
-            0x1E => {
-                let addn: (u16, bool) = self.idx.overflowing_add(self.regs[x as usize] as u16);
-                self.idx = addn.0;
+            Instruction::And { x, y } => {
+                self.regs[x as usize] &= self.regs[y as usize];
+                if self.config.quirks.logic_resets_vf { self.regs[0xF] = 0 }
             },
-            0x0A => {
-                self.status = CpuStatus::AwaitingKeyPress;
+            Instruction::Xor { x, y } => {
+                self.regs[x as usize] ^= self.regs[y as usize];
+                if self.config.quirks.logic_resets_vf { self.regs[0xF] = 0 }
             },
-            0x29 => self.idx = (self.regs[x as usize] as u16) * 5,
-            0x33 => {
+            Instruction::AddReg { x, y } => {
+                let addn: (u8, bool) = self.regs[x as usize].overflowing_add(self.regs[y as usize]);
+                self.regs[x as usize] = addn.0;
+                self.regs[0xF] = addn.1 as u8;
+            },
+            Instruction::Sub { x, y } => {
                 let xval = self.regs[x as usize];
-                let ones = xval % 10;
-                let tens = (xval / 10) % 10;
-                let hundreds = (xval / 100) % 10;
-                self.mem[self.idx as usize] = hundreds;
-                self.mem[(self.idx + 1) as usize] = tens;
-                self.mem[(self.idx + 2) as usize] = ones;
-            },
-            0x55 => {
+                let yval = self.regs[y as usize];
+                self.regs[x as usize] = xval.wrapping_sub(yval);
+                self.regs[0xF] = (xval > yval) as u8;
+            },
+            Instruction::Subn { x, y } => {
+                let xval = self.regs[x as usize];
+                let yval = self.regs[y as usize];
+                self.regs[x as usize] = yval.wrapping_sub(xval);
+                self.regs[0xF] = (yval > xval) as u8;
+            },
+            Instruction::Shr { x, y } => {
+                let source = if self.config.quirks.shift_reads_vy { self.regs[y as usize] } else { self.regs[x as usize] };
+                self.regs[x as usize] = source >> 1;
+                self.regs[0xF] = source & 0x1;
+            },
+            Instruction::Shl { x, y } => {
+                let source = if self.config.quirks.shift_reads_vy { self.regs[y as usize] } else { self.regs[x as usize] };
+                self.regs[x as usize] = source << 1;
+                self.regs[0xF] = source >> 7;
+            },
+            Instruction::LdI(addr) => self.idx = addr,
+            Instruction::JpV0(addr) => {
+                let offset_reg = if self.config.quirks.jump_uses_vx { (addr >> 8) as usize } else { 0 };
+                self.pc = (self.regs[offset_reg] as u16) + addr;
+            },
+            Instruction::Rnd { x, byte } => {
+                self.regs[x as usize] = self.rng.next_byte() & byte;
+            },
+            Instruction::Drw { x, y, n } => Self::draw(self, x, y, n)?,
+            Instruction::Skp(x) => Self::skip_if(self, self.keyboard[(self.regs[x as usize] & 0xF) as usize]),
+            Instruction::Sknp(x) => Self::skip_if(self, !self.keyboard[(self.regs[x as usize] & 0xF) as usize]),
+            Instruction::LdILong(addr) => self.idx = addr,
+            Instruction::Plane(mask) => self.planes = mask & 0x3,
+            Instruction::Audio => {
+                for number in 0..constants::AUDIO_PATTERN_SIZE {
+                    self.audio_pattern[number] = Self::read(self, self.idx as usize + number)?;
+                }
+            },
+            Instruction::LdVxDt(x) => self.regs[x as usize] = self.delay,
+            Instruction::LdVxK(x) => self.status = CpuStatus::AwaitingKeyPress { x },
+            Instruction::LdDtVx(x) => self.delay = self.regs[x as usize],
+            Instruction::LdStVx(x) => self.sound = self.regs[x as usize],
+            Instruction::AddI(x) => self.idx = self.idx.wrapping_add(self.regs[x as usize] as u16),
+            Instruction::LdF(x) => self.idx = self.config.font_address.wrapping_add((self.regs[x as usize] as u16) * 5),
+            Instruction::LdHf(x) => {
+                let big_font_address = self.config.font_address + FONT_SIZE as u16;
+                self.idx = big_font_address + ((self.regs[x as usize] & 0xF) as u16) * 10;
+            },
+            Instruction::Bcd(x) => {
+                let xval = self.regs[x as usize];
+                Self::write(self, self.idx as usize, xval / 100)?;
+                Self::write(self, self.idx as usize + 1, (xval / 10) % 10)?;
+                Self::write(self, self.idx as usize + 2, xval % 10)?;
+            },
+            Instruction::Pitch(x) => self.pitch = self.regs[x as usize],
+            Instruction::Store(x) => {
                 for number in 0..(x + 1) as usize {
-                    self.mem[(self.idx as usize) + number] = self.regs[number];
+                    Self::write(self, (self.idx as usize) + number, self.regs[number])?;
+                }
+                if self.config.quirks.load_store_increments_i {
+                    self.idx = self.idx.wrapping_add(x as u16 + 1);
                 }
             },
-            0x65 => {
+            Instruction::Load(x) => {
                 for number in 0..(x + 1) as usize {
-                    self.regs[number] = self.mem[(self.idx as usize) + number];
+                    self.regs[number] = Self::read(self, (self.idx as usize) + number)?;
+                }
+                if self.config.quirks.load_store_increments_i {
+                    self.idx = self.idx.wrapping_add(x as u16 + 1);
+                }
+            },
+            Instruction::SaveFlags(x) => {
+                let count = (x as usize + 1).min(constants::NUM_RPL_FLAGS);
+                self.rpl[..count].copy_from_slice(&self.regs[..count]);
+            },
+            Instruction::LoadFlags(x) => {
+                let count = (x as usize + 1).min(constants::NUM_RPL_FLAGS);
+                self.regs[..count].copy_from_slice(&self.rpl[..count]);
+            },
+        }
+        Ok(())
+    }
+
+    fn register_range(x: u8, y: u8) -> Box<dyn Iterator<Item = usize>> {
+        if x <= y {
+            Box::new(x as usize..=y as usize)
+        } else {
+            Box::new((y as usize..=x as usize).rev())
+        }
+    }
+
+    fn draw(&mut self, x: u8, y: u8, n: u8) -> Result<(), Chip8Error> {
+        let xcoord = self.regs[x as usize] as usize;
+        let ycoord = self.regs[y as usize] as usize;
+        let wrap = self.config.quirks.wrap_sprites;
+        //SUPER-CHIP DXY0 draws a 16x16 sprite stored as two bytes per row
+        let (row_count, row_width) = if n == 0 && self.config.platform >= Platform::SuperChip { (16, 16) } else { (n as usize, 8) };
+        let bytes_per_plane = row_count * row_width / 8;
+        //Each selected plane consumes its own sprite, stored one after another starting at I
+        let mut address = self.idx as usize;
+        let mut collision = false;
+        for plane_number in 0..constants::NUM_PLANES {
+            let plane = 1 << plane_number;
+            if self.planes & plane == 0 {
+                continue;
+            }
+            let mut rows: Vec<u16> = Vec::with_capacity(row_count);
+            for number in 0..row_count {
+                if row_width == 16 {
+                    rows.push(Self::read_word(self, address + number * 2)?);
+                } else {
+                    rows.push(Self::read(self, address + number)? as u16);
                 }
             }
-            _ => panic!("Unsupported opcode")
+            collision |= self.screen.draw_sprite(xcoord, ycoord, &rows, row_width, plane, wrap);
+            address += bytes_per_plane;
+        }
+        self.regs[0xF] = collision as u8;
+        self.redraw = true;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An XO-CHIP loop that rewrites code it has already run. `F155` stores `V0 V1` over the
+    /// `V4 += NN` at 0x208 so that it adds the loop count, and `F333` writes the BCD digits of
+    /// the loop count into the operand of the `i := long` at 0x214. The loop stops after 50
+    /// passes, before the hundreds digit stops being zero.
+    const SELF_MODIFYING_ROM: [u8; 36] = [
+        0x63, 0x00, //0x200 V3 := 0
+        0x64, 0x00, //0x202 V4 := 0
+        0x60, 0x74, //0x204 V0 := 0x74
+        0x73, 0x01, //0x206 loop: V3 += 1
+        0x74, 0x01, //0x208 V4 += 1, rewritten to V4 += V3 of the previous pass
+        0x81, 0x30, //0x20A V1 := V3
+        0xA2, 0x08, //0x20C I := 0x208
+        0xF1, 0x55, //0x20E save V1
+        0xA2, 0x15, //0x210 I := 0x215
+        0xF3, 0x33, //0x212 bcd V3
+        0xF0, 0x00, 0x00, 0x00, //0x214 i := long 0x0000, rewritten to the BCD digits
+        0xF0, 0x65, //0x218 load V0
+        0x85, 0x00, //0x21A V5 := V0
+        0x60, 0x74, //0x21C V0 := 0x74
+        0x33, 0x32, //0x21E if V3 != 50 then
+        0x12, 0x06, //0x220 jump loop
+        0x12, 0x22, //0x222 jump 0x222
+    ];
+
+    fn run(decode_cache: bool) -> Chip8 {
+        let config = Config { decode_cache, ..Config::new(Platform::XoChip) };
+        let mut chip8 = Chip8::new(&SELF_MODIFYING_ROM, config).unwrap();
+        let mut scheduler = FrameScheduler::new(600);
+        for _ in 0..120 {
+            scheduler.run_frame(&mut chip8, [false; 16]).unwrap();
+        }
+        chip8
+    }
+
+    #[test]
+    fn decode_cache_does_not_change_self_modifying_code() {
+        let uncached = run(false);
+        let cached = run(true);
+        assert_eq!(uncached.pc(), 0x222, "the loop should have finished");
+        //1 on the first pass, then 1 + 2 + ... + 49 from the rewritten instruction
+        assert_eq!(uncached.registers()[4], ((1 + 49 * 50 / 2) % 256) as u8);
+        //The digits 0 5 0 make i := long 0x0500, and the load leaves I one past it
+        assert_eq!(uncached.index(), 0x0501);
+        assert!(cached.save_state() == uncached.save_state(), "the decode cache changed the outcome");
+    }
+
+    #[test]
+    fn halted_interpreters_stay_halted() {
+        //v1 := 5, an unknown opcode, v1 := 6
+        let mut chip8 = Chip8::new(&[0x61, 0x05, 0x00, 0x00, 0x61, 0x06], Config::default()).unwrap();
+        chip8.tick([false; 16]).unwrap();
+        assert_eq!(chip8.tick([false; 16]), Err(Chip8Error::UnknownOpcode { address: 0x202, opcode: 0x0000 }));
+        assert_eq!(chip8.status, CpuStatus::Halted);
+        let (pc, registers) = (chip8.pc(), *chip8.registers());
+        for _ in 0..3 {
+            assert_eq!(chip8.tick([false; 16]), Ok(()));
+        }
+        assert_eq!((chip8.pc(), *chip8.registers()), (pc, registers));
+        assert_eq!(chip8.status, CpuStatus::Halted);
+
+        chip8.reset();
+        assert_eq!(chip8.status, CpuStatus::Running);
+        chip8.tick([false; 16]).unwrap();
+        assert_eq!(chip8.registers()[1], 5);
+    }
+
+    #[test]
+    fn exited_interpreters_stay_exited() {
+        //exit, v1 := 6
+        let mut chip8 = Chip8::new(&[0x00, 0xFD, 0x61, 0x06], Config::new(Platform::SuperChip)).unwrap();
+        chip8.tick([false; 16]).unwrap();
+        assert_eq!(chip8.status, CpuStatus::Exited);
+        let pc = chip8.pc();
+        chip8.tick([false; 16]).unwrap();
+        assert_eq!((chip8.pc(), chip8.registers()[1]), (pc, 0));
+    }
+
+    #[test]
+    fn rejects_impossible_configs() {
+        let cases = [
+            (Config { memory_size: constants::MAX_MEMORY_SIZE + 1, ..Config::default() }, "memory is larger than the 64 KiB address space"),
+            (Config { memory_size: 0x40, ..Config::default() }, "memory is too small to hold the font"),
+            (Config { font_address: 0xFFC, ..Config::default() }, "memory is too small to hold the font"),
+            (Config { load_address: 0x1000, ..Config::default() }, "load address is outside memory"),
+            (Config { stack_depth: 0, ..Config::default() }, "stack depth must be at least 1"),
+        ];
+        for (config, reason) in cases.iter() {
+            assert_eq!(Chip8::new(&[], *config).err(), Some(Chip8Error::InvalidConfig(reason)), "{:?}", config);
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn rejects_roms_larger_than_the_space_after_the_load_address() {
+        let config = Config { memory_size: 0x800, load_address: 0x600, ..Config::default() };
+        assert!(Chip8::new(&[0; 0x200], config).is_ok());
+        assert_eq!(Chip8::new(&[0; 0x201], config).err(), Some(Chip8Error::RomTooLarge { size: 0x201, capacity: 0x200 }));
+    }
+
+    #[test]
+    fn starts_at_the_load_address() {
+        let config = Config { load_address: 0x600, ..Config::default() };
+        let chip8 = Chip8::new(&[0x12, 0x34], config).unwrap();
+        assert_eq!(chip8.pc(), 0x600);
+        assert_eq!(&chip8.memory()[0x600..0x602], [0x12, 0x34]);
+    }
+
+    #[test]
+    fn calls_overflow_at_the_configured_stack_depth() {
+        //0x200: call 0x200
+        let config = Config { stack_depth: 3, ..Config::default() };
+        let mut chip8 = Chip8::new(&[0x22, 0x00], config).unwrap();
+        for _ in 0..3 {
+            chip8.tick([false; 16]).unwrap();
+        }
+        assert_eq!(chip8.stack(), [0x202, 0x202, 0x202]);
+        assert_eq!(chip8.tick([false; 16]), Err(Chip8Error::StackOverflow { address: 0x200 }));
+    }
+}
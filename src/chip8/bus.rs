@@ -0,0 +1,213 @@
+use std::ops::Range;
+
+/// Observes or intercepts the interpreter's memory accesses within an address range, e.g. for
+/// watchpoints, cheats, access heatmaps or memory-mapped peripherals. Every method defaults to
+/// doing nothing, so a hook only implements the accesses it cares about.
+///
+/// Instruction fetches are reported through `execute` only, never through `read`, so a hook
+/// cannot change the program the interpreter decodes.
+pub trait BusHook: Send {
+    /// The instruction at `address` is about to execute.
+    fn execute(&mut self, _address: u16) {}
+
+    /// A data read, e.g. by DXYN or FX65. The returned value is what the interpreter sees.
+    fn read(&mut self, _address: usize, value: u8) -> u8 {
+        value
+    }
+
+    /// A write, e.g. by FX33 or FX55. The returned value is what ends up in memory.
+    fn write(&mut self, _address: usize, value: u8) -> u8 {
+        value
+    }
+}
+
+/// Identifies a hook attached with `Chip8::add_hook`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HookId(usize);
+
+struct Hook {
+    id: HookId,
+    range: Range<usize>,
+    hook: Box<dyn BusHook>,
+}
+
+/// The address space the interpreter reads, writes and executes through. Without any hooks an
+/// access costs one emptiness check on top of the bounds check, so plain execution is not slowed
+/// down by the hook machinery.
+pub struct Bus {
+    mem: Vec<u8>,
+    hooks: Vec<Hook>,
+    next_id: usize,
+}
+
+impl Bus {
+    pub(super) fn new(size: usize) -> Self {
+        Self {
+            mem: vec![0; size],
+            hooks: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.mem
+    }
+
+    /// Direct access that bypasses the hooks, for loading fonts, ROMs and save states.
+    pub(super) fn memory_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.mem
+    }
+
+    pub fn len(&self) -> usize {
+        self.mem.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mem.is_empty()
+    }
+
+    #[inline]
+    pub(super) fn read(&mut self, address: usize) -> Option<u8> {
+        let value = *self.mem.get(address)?;
+        if self.hooks.is_empty() {
+            return Some(value);
+        }
+        Some(self.hooks.iter_mut()
+            .filter(|h| h.range.contains(&address))
+            .fold(value, |value, h| h.hook.read(address, value)))
+    }
+
+    /// Stores `value` unless `address` is out of range, returning whether it did.
+    #[inline]
+    pub(super) fn write(&mut self, address: usize, value: u8) -> bool {
+        if address >= self.mem.len() {
+            return false;
+        }
+        let value = if self.hooks.is_empty() {
+            value
+        } else {
+            self.hooks.iter_mut()
+                .filter(|h| h.range.contains(&address))
+                .fold(value, |value, h| h.hook.write(address, value))
+        };
+        self.mem[address] = value;
+        true
+    }
+
+    #[inline]
+    pub(super) fn execute(&mut self, address: u16) {
+        if self.hooks.is_empty() {
+            return;
+        }
+        for h in self.hooks.iter_mut().filter(|h| h.range.contains(&(address as usize))) {
+            h.hook.execute(address);
+        }
+    }
+
+    pub(super) fn add_hook(&mut self, range: Range<usize>, hook: Box<dyn BusHook>) -> HookId {
+        let id = HookId(self.next_id);
+        self.next_id += 1;
+        self.hooks.push(Hook { id, range, hook });
+        id
+    }
+
+    pub(super) fn remove_hook(&mut self, id: HookId) -> Option<Box<dyn BusHook>> {
+        let position = self.hooks.iter().position(|h| h.id == id)?;
+        Some(self.hooks.remove(position).hook)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::chip8::{Chip8, Config};
+
+    /// Stores 0x42 at 0x300, loads it back into V0 and spins at 0x20A.
+    const ROM: [u8; 12] = [0xA3, 0x00, 0x60, 0x42, 0xF0, 0x55, 0xA3, 0x00, 0xF0, 0x65, 0x12, 0x0A];
+
+    /// Records the accesses it sees, and flips written bits and increments read values.
+    #[derive(Default)]
+    struct Recorder {
+        executed: Arc<Mutex<Vec<u16>>>,
+        reads: Arc<Mutex<Vec<usize>>>,
+        writes: Arc<Mutex<Vec<usize>>>,
+    }
+
+    impl BusHook for Recorder {
+        fn execute(&mut self, address: u16) {
+            self.executed.lock().unwrap().push(address);
+        }
+
+        fn read(&mut self, address: usize, value: u8) -> u8 {
+            self.reads.lock().unwrap().push(address);
+            value.wrapping_add(1)
+        }
+
+        fn write(&mut self, address: usize, value: u8) -> u8 {
+            self.writes.lock().unwrap().push(address);
+            !value
+        }
+    }
+
+    fn run(chip8: &mut Chip8, instructions: usize) {
+        for _ in 0..instructions {
+            chip8.tick([false; 16]).unwrap();
+        }
+    }
+
+    #[test]
+    fn hooks_override_reads_and_writes() {
+        let mut chip8 = Chip8::new(&ROM, Config::default()).unwrap();
+        let hook = Recorder::default();
+        let (reads, writes) = (hook.reads.clone(), hook.writes.clone());
+        chip8.add_hook(0x300..0x301, Box::new(hook));
+        run(&mut chip8, 5);
+        assert_eq!(*writes.lock().unwrap(), [0x300]);
+        assert_eq!(*reads.lock().unwrap(), [0x300]);
+        assert_eq!(chip8.memory()[0x300], !0x42);
+        assert_eq!(chip8.registers()[0], (!0x42_u8).wrapping_add(1));
+    }
+
+    #[test]
+    fn fetches_are_reported_as_execution_only() {
+        let mut chip8 = Chip8::new(&ROM, Config::default()).unwrap();
+        let hook = Recorder::default();
+        let (executed, reads) = (hook.executed.clone(), hook.reads.clone());
+        chip8.add_hook(0x200..0x20A, Box::new(hook));
+        run(&mut chip8, 7);
+        assert_eq!(*executed.lock().unwrap(), [0x200, 0x202, 0x204, 0x206, 0x208]);
+        assert!(reads.lock().unwrap().is_empty());
+        assert_eq!(chip8.registers()[0], 0x42);
+    }
+
+    #[test]
+    fn removed_hooks_stop_seeing_accesses() {
+        let mut chip8 = Chip8::new(&ROM, Config::default()).unwrap();
+        let hook = Recorder::default();
+        let executed = hook.executed.clone();
+        let id = chip8.add_hook(0x200..0x20C, Box::new(hook));
+        run(&mut chip8, 2);
+        assert!(chip8.remove_hook(id).is_some());
+        assert!(chip8.remove_hook(id).is_none());
+        run(&mut chip8, 3);
+        assert_eq!(*executed.lock().unwrap(), [0x200, 0x202]);
+        assert_eq!(chip8.memory()[0x300], 0x42);
+    }
+
+    #[test]
+    fn overlapping_hooks_apply_in_the_order_they_were_added() {
+        let mut bus = Bus::new(16);
+        bus.add_hook(0..16, Box::new(Recorder::default()));
+        bus.add_hook(4..8, Box::new(Recorder::default()));
+        assert!(bus.write(5, 0x0F));
+        assert!(bus.write(9, 0x0F));
+        assert!(!bus.write(16, 0x0F));
+        assert_eq!(bus.memory()[5], 0x0F);
+        assert_eq!(bus.memory()[9], 0xF0);
+        assert_eq!(bus.read(5), Some(0x11));
+        assert_eq!(bus.read(9), Some(0xF1));
+        assert_eq!(bus.read(16), None);
+    }
+}
@@ -0,0 +1,38 @@
+use super::Instruction;
+
+/// Decoded instructions keyed by the address of their first byte. Any write that overlaps a
+/// cached instruction must go through `invalidate` so self-modifying code is re-decoded.
+pub struct DecodeCache {
+    entries: Vec<Option<Instruction>>,
+}
+
+impl DecodeCache {
+    pub fn new(memory_size: usize) -> Self {
+        Self {
+            entries: vec![None; memory_size],
+        }
+    }
+
+    pub fn get(&self, address: usize) -> Option<Instruction> {
+        match self.entries.get(address) {
+            Some(entry) => *entry,
+            None => None,
+        }
+    }
+
+    pub fn insert(&mut self, address: usize, instruction: Instruction) {
+        if let Some(entry) = self.entries.get_mut(address) {
+            *entry = Some(instruction);
+        }
+    }
+
+    /// Drops every entry whose encoding covers `target`. Instructions are at most four bytes
+    /// long, so only the three preceding addresses can overlap it.
+    pub fn invalidate(&mut self, target: usize) {
+        for address in target.saturating_sub(3)..=target {
+            if let Some(entry) = self.entries.get_mut(address) {
+                *entry = None;
+            }
+        }
+    }
+}
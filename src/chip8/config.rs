@@ -0,0 +1,135 @@
+use std::fmt;
+
+use super::{constants, FontSet, Quirks, DEFAULT_SEED};
+
+/// The instruction set the interpreter accepts, ordered so that each platform is a superset of
+/// the ones before it. Opcodes from a later platform are reported as unknown on an earlier one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Platform {
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl Platform {
+    pub const ALL: [Platform; 3] = [Platform::Chip8, Platform::SuperChip, Platform::XoChip];
+
+    /// The quirks a new `Config` starts with. Plain CHIP-8 keeps the interpreter's original
+    /// behaviour; the later platforms use the quirks most ROMs written for them expect.
+    pub fn default_quirks(&self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks::default(),
+            Platform::SuperChip => Quirks::superchip(),
+            Platform::XoChip => Quirks::xochip(),
+        }
+    }
+
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Platform::XoChip => constants::XO_MEMORY_SIZE,
+            _ => constants::MEMORY_SIZE,
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Platform::Chip8 => "CHIP-8",
+            Platform::SuperChip => "SUPER-CHIP 1.1",
+            Platform::XoChip => "XO-CHIP",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Everything that is fixed for the lifetime of a `Chip8`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    pub platform: Platform,
+    pub quirks: Quirks,
+    /// Bytes of addressable memory, from a 2 KiB COSMAC VIP up to the 64 KiB of XO-CHIP.
+    pub memory_size: usize,
+    /// Nesting levels of 2NNN before a call overflows the stack.
+    pub stack_depth: usize,
+    /// Where the ROM is loaded and execution starts, e.g. 0x600 for ETI-660 programs.
+    pub load_address: u16,
+    pub font: FontSet,
+    /// Where the small font is loaded and FX29 points. The SUPER-CHIP big font follows it.
+    pub font_address: u16,
+    /// Keep decoded instructions per address instead of decoding on every fetch. Execution is
+    /// identical either way; the cache only pays off when running many instructions headlessly.
+    pub decode_cache: bool,
+    /// Seed for the default random number generator behind CXNN.
+    pub seed: u64,
+}
+
+impl Config {
+    pub fn new(platform: Platform) -> Self {
+        Self {
+            platform,
+            quirks: platform.default_quirks(),
+            memory_size: platform.memory_size(),
+            stack_depth: constants::STACK_DEPTH,
+            load_address: constants::PC_INITIAL,
+            font: FontSet::SuperChip,
+            font_address: constants::FONT_ADDRESS,
+            decode_cache: false,
+            seed: DEFAULT_SEED,
+        }
+    }
+
+    /// Switches to `platform` and its quirks, keeping the memory layout, stack and font the user
+    /// picked. Memory still at the old platform's default size follows the new platform's.
+    pub fn set_platform(&mut self, platform: Platform) {
+        if self.memory_size == self.platform.memory_size() {
+            self.memory_size = platform.memory_size();
+        }
+        self.platform = platform;
+        self.quirks = platform.default_quirks();
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new(Platform::Chip8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_platform_keeps_the_users_layout_and_font() {
+        let mut config = Config::new(Platform::Chip8);
+        config.memory_size = 2048;
+        config.stack_depth = 12;
+        config.load_address = 0x600;
+        config.font = FontSet::CosmacVip;
+        config.font_address = 0x100;
+        config.set_platform(Platform::XoChip);
+        assert_eq!(config.platform, Platform::XoChip);
+        assert_eq!(config.quirks, Quirks::xochip());
+        assert_eq!((config.memory_size, config.stack_depth, config.load_address), (2048, 12, 0x600));
+        assert_eq!((config.font, config.font_address), (FontSet::CosmacVip, 0x100));
+    }
+
+    #[test]
+    fn chip8_defaults_to_the_original_behaviour() {
+        let quirks = Config::default().quirks;
+        assert_eq!(quirks, Quirks::default());
+        assert!(!quirks.shift_reads_vy && !quirks.load_store_increments_i && !quirks.jump_uses_vx);
+        assert!(!quirks.logic_resets_vf && !quirks.wrap_sprites);
+        assert_eq!(Config::new(Platform::SuperChip).quirks, Quirks::superchip());
+    }
+
+    #[test]
+    fn set_platform_follows_the_default_memory_size() {
+        let mut config = Config::new(Platform::Chip8);
+        config.set_platform(Platform::XoChip);
+        assert_eq!(config.memory_size, constants::XO_MEMORY_SIZE);
+        config.set_platform(Platform::SuperChip);
+        assert_eq!(config.memory_size, constants::MEMORY_SIZE);
+    }
+}
@@ -0,0 +1,46 @@
+use super::{Chip8, Instruction};
+
+/// Where a debugger run that spans several instructions should stop, checked after every
+/// instruction with `reached`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunTarget {
+    /// `pc` reaches `address` with no more than `depth` return addresses on the stack, so a
+    /// recursive call passing through the same address does not count.
+    Address { address: u16, depth: usize },
+    /// A return leaves fewer than `depth` return addresses on the stack.
+    Return { depth: usize },
+}
+
+impl RunTarget {
+    /// Runs a `2NNN` call at `pc` to completion. Any other instruction is a single step, which
+    /// the caller performs itself, so this returns `None`.
+    pub fn step_over(chip8: &Chip8) -> Option<Self> {
+        match chip8.current_instruction() {
+            Ok(instruction @ Instruction::Call(_)) => Some(RunTarget::Address {
+                address: chip8.pc().wrapping_add(instruction.size()),
+                depth: chip8.stack().len(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Runs until the current subroutine returns, or `None` outside of any subroutine.
+    pub fn step_out(chip8: &Chip8) -> Option<Self> {
+        match chip8.stack().len() {
+            0 => None,
+            depth => Some(RunTarget::Return { depth }),
+        }
+    }
+
+    /// Runs until `pc` reaches `address` at any call depth.
+    pub fn run_to(address: u16) -> Self {
+        RunTarget::Address { address, depth: usize::MAX }
+    }
+
+    pub fn reached(&self, chip8: &Chip8) -> bool {
+        match *self {
+            RunTarget::Address { address, depth } => chip8.pc() == address && chip8.stack().len() <= depth,
+            RunTarget::Return { depth } => chip8.stack().len() < depth,
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use super::constants;
+
+/// The framebuffer in row-major order. Each byte holds one pixel as a bitmask of the XO-CHIP
+/// bitplanes that are lit there, so plain CHIP-8 and SUPER-CHIP programs only ever set bit 0.
+/// Its size follows the resolution mode: 64x32 normally, 128x64 after a `hires` instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Display {
+    hires: bool,
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Display {
+    pub fn new() -> Self {
+        let width = constants::SCREEN_WIDTH as usize;
+        let height = constants::SCREEN_HEIGHT as usize;
+        Self {
+            hires: false,
+            width,
+            height,
+            pixels: vec![0; width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    /// One plane bitmask per pixel; see `constants::NUM_PLANES`.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.pixels[x + y * self.width]
+    }
+
+    /// One line of text per row: `.` for unlit pixels, `#` for plane 1, `+` for plane 2 and `@`
+    /// for pixels lit in both, so framebuffers can be compared and diffed as plain text.
+    pub fn to_ascii(&self) -> String {
+        let mut text = String::with_capacity((self.width + 1) * self.height);
+        for row in self.pixels.chunks(self.width) {
+            text.extend(row.iter().map(|pixel| match pixel & 0x3 {
+                0 => '.',
+                1 => '#',
+                2 => '+',
+                _ => '@',
+            }));
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Clears the planes selected in `planes`, leaving the others untouched.
+    pub fn clear(&mut self, planes: u8) {
+        for pixel in self.pixels.iter_mut() {
+            *pixel &= !planes;
+        }
+    }
+
+    /// Switches resolution; every plane is cleared as it was on the HP-48.
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        if hires {
+            self.width = constants::HIRES_SCREEN_WIDTH as usize;
+            self.height = constants::HIRES_SCREEN_HEIGHT as usize;
+        } else {
+            self.width = constants::SCREEN_WIDTH as usize;
+            self.height = constants::SCREEN_HEIGHT as usize;
+        }
+        self.pixels = vec![0; self.width * self.height];
+    }
+
+    /// Overwrites the whole framebuffer; `pixels` must match the current resolution.
+    pub(super) fn load_pixels(&mut self, pixels: &[u8]) {
+        self.pixels.copy_from_slice(pixels);
+    }
+
+    /// XORs a sprite into a single plane and returns whether any lit pixel of that plane was
+    /// turned off. Each entry of `rows` holds `row_width` bits, most significant bit leftmost.
+    pub fn draw_sprite(&mut self, x: usize, y: usize, rows: &[u16], row_width: usize, plane: u8, wrap: bool) -> bool {
+        let xcoord = x % self.width;
+        let ycoord = y % self.height;
+        let mut collision = false;
+        for (number, line) in rows.iter().enumerate() {
+            let mut row = ycoord + number;
+            if row >= self.height {
+                if !wrap {
+                    continue;
+                }
+                row %= self.height;
+            }
+            for bit in 0..row_width {
+                let mut col = xcoord + bit;
+                if col >= self.width {
+                    if !wrap {
+                        continue;
+                    }
+                    col %= self.width;
+                }
+                if (line >> (row_width - 1 - bit)) & 0x1 == 0 {
+                    continue;
+                }
+                let pix_idx = col + row * self.width;
+                if self.pixels[pix_idx] & plane != 0 {
+                    collision = true;
+                }
+                self.pixels[pix_idx] ^= plane;
+            }
+        }
+        collision
+    }
+
+    pub fn scroll_down(&mut self, rows: usize, planes: u8) {
+        let shift = rows.min(self.height) * self.width;
+        for target in (0..self.pixels.len()).rev() {
+            let source = if target >= shift { self.pixels[target - shift] } else { 0 };
+            self.pixels[target] = (self.pixels[target] & !planes) | (source & planes);
+        }
+    }
+
+    pub fn scroll_up(&mut self, rows: usize, planes: u8) {
+        let shift = rows.min(self.height) * self.width;
+        let len = self.pixels.len();
+        for target in 0..len {
+            let source = if target + shift < len { self.pixels[target + shift] } else { 0 };
+            self.pixels[target] = (self.pixels[target] & !planes) | (source & planes);
+        }
+    }
+
+    pub fn scroll_right(&mut self, cols: usize, planes: u8) {
+        let cols = cols.min(self.width);
+        for row in self.pixels.chunks_mut(self.width) {
+            for target in (0..row.len()).rev() {
+                let source = if target >= cols { row[target - cols] } else { 0 };
+                row[target] = (row[target] & !planes) | (source & planes);
+            }
+        }
+    }
+
+    pub fn scroll_left(&mut self, cols: usize, planes: u8) {
+        let cols = cols.min(self.width);
+        for row in self.pixels.chunks_mut(self.width) {
+            let len = row.len();
+            for target in 0..len {
+                let source = if target + cols < len { row[target + cols] } else { 0 };
+                row[target] = (row[target] & !planes) | (source & planes);
+            }
+        }
+    }
+}
+
+impl Default for Display {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,71 @@
+use std::fmt;
+
+/// Everything that can stop the interpreter. The addresses reported are those of the
+/// instruction that faulted, not of the program counter after the fetch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chip8Error {
+    UnknownOpcode { address: u16, opcode: u16 },
+    StackOverflow { address: u16 },
+    StackUnderflow { address: u16 },
+    MemoryOutOfBounds { address: u16, target: usize },
+    RomTooLarge { size: usize, capacity: usize },
+    /// The `Config` describes a machine that cannot exist, e.g. one whose load address lies
+    /// outside its memory.
+    InvalidConfig(&'static str),
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chip8Error::UnknownOpcode { address, opcode } => {
+                write!(f, "unknown opcode {:04X} at {:03X}", opcode, address)
+            },
+            Chip8Error::StackOverflow { address } => {
+                write!(f, "stack overflow on call at {:03X}", address)
+            },
+            Chip8Error::StackUnderflow { address } => {
+                write!(f, "stack underflow on return at {:03X}", address)
+            },
+            Chip8Error::MemoryOutOfBounds { address, target } => {
+                write!(f, "memory access to {:X} out of bounds at {:03X}", target, address)
+            },
+            Chip8Error::RomTooLarge { size, capacity } => {
+                write!(f, "ROM is {} bytes but only {} bytes are available", size, capacity)
+            },
+            Chip8Error::InvalidConfig(reason) => write!(f, "invalid machine configuration: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {}
+
+/// Why a save state could not be restored. The interpreter is left untouched in every case.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateError {
+    /// The data does not start with the save state magic number.
+    NotASaveState,
+    /// The save state was written by a newer format version than this build understands.
+    UnsupportedVersion { version: u16, supported: u16 },
+    /// The data ends before the state is complete.
+    Truncated,
+    /// The state was saved from a machine with a different platform or memory size.
+    MachineMismatch,
+    /// A field holds a value the interpreter can never be in.
+    Corrupt,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::NotASaveState => write!(f, "not a save state"),
+            StateError::UnsupportedVersion { version, supported } => {
+                write!(f, "save state version {} is newer than the supported version {}", version, supported)
+            },
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::MachineMismatch => write!(f, "save state was made for a different machine configuration"),
+            StateError::Corrupt => write!(f, "save state is corrupt"),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
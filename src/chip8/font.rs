@@ -0,0 +1,155 @@
+use std::fmt;
+
+use super::{constants, Chip8Error};
+
+/// Bytes in a small hex font: sixteen glyphs of five rows each.
+pub const FONT_SIZE: usize = 80;
+
+const COSMAC_VIP_FONT: [u8; FONT_SIZE] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x60, 0x20, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0xA0, 0xA0, 0xF0, 0x20, 0x20, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x10, 0x10, 0x10, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xF0, 0x50, 0x70, 0x50, 0xF0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xF0, 0x50, 0x50, 0x50, 0xF0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+const DREAM_6800_FONT: [u8; FONT_SIZE] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x40, 0x40, 0x40, 0x40, 0x40, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0x80, 0xA0, 0xA0, 0xE0, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0xC0, 0xA0, 0xE0, 0xA0, 0xC0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+const ETI_660_FONT: [u8; FONT_SIZE] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x20, 0x20, 0x20, 0x20, 0x20, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0xA0, 0xA0, 0xE0, 0x20, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0x80, 0x80, 0xE0, 0xA0, 0xE0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0x20, 0x20, 0xE0, 0xA0, 0xE0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+/// The small hex font FX29 points into. Interpreters shipped visibly different glyphs, and
+/// some ROMs draw the digits as part of their graphics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FontSet {
+    CosmacVip,
+    Dream6800,
+    Eti660,
+    /// The CHIP-48 and SUPER-CHIP font most modern interpreters use.
+    SuperChip,
+    /// Glyphs loaded from a font file with `FontSet::parse`.
+    Custom([u8; FONT_SIZE]),
+}
+
+impl FontSet {
+    pub const BUILT_IN: [FontSet; 4] = [FontSet::CosmacVip, FontSet::Dream6800, FontSet::Eti660, FontSet::SuperChip];
+
+    pub fn data(&self) -> &[u8; FONT_SIZE] {
+        match self {
+            FontSet::CosmacVip => &COSMAC_VIP_FONT,
+            FontSet::Dream6800 => &DREAM_6800_FONT,
+            FontSet::Eti660 => &ETI_660_FONT,
+            FontSet::SuperChip => &constants::FONT_DATA,
+            FontSet::Custom(data) => data,
+        }
+    }
+
+    /// Reads a custom font file holding exactly the 80 bytes of sixteen 5-row glyphs.
+    pub fn parse(bytes: &[u8]) -> Result<Self, Chip8Error> {
+        if bytes.len() != FONT_SIZE {
+            return Err(Chip8Error::InvalidConfig("a font file must hold exactly 80 bytes"));
+        }
+        let mut data = [0; FONT_SIZE];
+        data.copy_from_slice(bytes);
+        Ok(FontSet::Custom(data))
+    }
+}
+
+impl fmt::Display for FontSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FontSet::CosmacVip => "COSMAC VIP",
+            FontSet::Dream6800 => "DREAM 6800",
+            FontSet::Eti660 => "ETI-660",
+            FontSet::SuperChip => "SUPER-CHIP",
+            FontSet::Custom(_) => "Custom",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::{Chip8, Config};
+
+    #[test]
+    fn parse_needs_exactly_one_font() {
+        for &len in [0, 1, FONT_SIZE - 1, FONT_SIZE + 1, 2 * FONT_SIZE].iter() {
+            assert_eq!(
+                FontSet::parse(&vec![0xF0; len]),
+                Err(Chip8Error::InvalidConfig("a font file must hold exactly 80 bytes")),
+                "{} bytes", len
+            );
+        }
+        let bytes: Vec<u8> = (0..FONT_SIZE as u8).collect();
+        let font = FontSet::parse(&bytes).unwrap();
+        assert_eq!(font.data()[..], bytes[..]);
+        assert_eq!(font.to_string(), "Custom");
+    }
+
+    #[test]
+    fn built_in_fonts_are_distinct() {
+        for (index, font) in FontSet::BUILT_IN.iter().enumerate() {
+            for other in FontSet::BUILT_IN[index + 1..].iter() {
+                assert_ne!(font.data(), other.data(), "{} and {}", font, other);
+            }
+        }
+    }
+
+    #[test]
+    fn hex_digits_point_into_the_font_at_its_address() {
+        let font = FontSet::parse(&[0xAA; FONT_SIZE]).unwrap();
+        let config = Config { font, font_address: 0x100, ..Config::default() };
+        //v0 := 7, i := hex v0
+        let mut chip8 = Chip8::new(&[0x60, 0x07, 0xF0, 0x29], config).unwrap();
+        assert_eq!(chip8.memory()[0x100..0x100 + FONT_SIZE], [0xAA; FONT_SIZE][..]);
+        chip8.tick([false; 16]).unwrap();
+        chip8.tick([false; 16]).unwrap();
+        assert_eq!(chip8.index(), 0x100 + 7 * 5);
+    }
+}
@@ -0,0 +1,475 @@
+use std::fmt;
+
+use super::Platform;
+
+/// A decoded CHIP-8, SUPER-CHIP or XO-CHIP instruction. Register operands are register indices
+/// (0x0-0xF), `byte` operands are immediates and `addr` operands are addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// 00CN
+    ScrollDown(u8),
+    /// 00DN
+    ScrollUp(u8),
+    /// 00E0
+    Cls,
+    /// 00EE
+    Ret,
+    /// 00FB
+    ScrollRight,
+    /// 00FC
+    ScrollLeft,
+    /// 00FD
+    Exit,
+    /// 00FE
+    Lores,
+    /// 00FF
+    Hires,
+    /// 1NNN
+    Jp(u16),
+    /// 2NNN
+    Call(u16),
+    /// 3XNN
+    SeByte { x: u8, byte: u8 },
+    /// 4XNN
+    SneByte { x: u8, byte: u8 },
+    /// 5XY0
+    SeReg { x: u8, y: u8 },
+    /// 5XY2
+    SaveRange { x: u8, y: u8 },
+    /// 5XY3
+    LoadRange { x: u8, y: u8 },
+    /// 6XNN
+    LdByte { x: u8, byte: u8 },
+    /// 7XNN
+    AddByte { x: u8, byte: u8 },
+    /// 8XY0
+    LdReg { x: u8, y: u8 },
+    /// 8XY1
+    Or { x: u8, y: u8 },
+    /// 8XY2
+    And { x: u8, y: u8 },
+    /// 8XY3
+    Xor { x: u8, y: u8 },
+    /// 8XY4
+    AddReg { x: u8, y: u8 },
+    /// 8XY5
+    Sub { x: u8, y: u8 },
+    /// 8XY6
+    Shr { x: u8, y: u8 },
+    /// 8XY7
+    Subn { x: u8, y: u8 },
+    /// 8XYE
+    Shl { x: u8, y: u8 },
+    /// 9XY0
+    SneReg { x: u8, y: u8 },
+    /// ANNN
+    LdI(u16),
+    /// BNNN, or BXNN with the `jump_uses_vx` quirk
+    JpV0(u16),
+    /// CXNN
+    Rnd { x: u8, byte: u8 },
+    /// DXYN
+    Drw { x: u8, y: u8, n: u8 },
+    /// EX9E
+    Skp(u8),
+    /// EXA1
+    Sknp(u8),
+    /// F000 NNNN
+    LdILong(u16),
+    /// FN01
+    Plane(u8),
+    /// F002
+    Audio,
+    /// FX07
+    LdVxDt(u8),
+    /// FX0A
+    LdVxK(u8),
+    /// FX15
+    LdDtVx(u8),
+    /// FX18
+    LdStVx(u8),
+    /// FX1E
+    AddI(u8),
+    /// FX29
+    LdF(u8),
+    /// FX30
+    LdHf(u8),
+    /// FX33
+    Bcd(u8),
+    /// FX3A
+    Pitch(u8),
+    /// FX55
+    Store(u8),
+    /// FX65
+    Load(u8),
+    /// FX75
+    SaveFlags(u8),
+    /// FX85
+    LoadFlags(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The word is not an instruction on any supported platform.
+    Unknown(u16),
+    /// The word is `F000`, whose address operand is in the following word; see `decode_long`.
+    NeedsOperand(u16),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Unknown(word) => write!(f, "unknown opcode {:04X}", word),
+            DecodeError::NeedsOperand(word) => write!(f, "opcode {:04X} needs a second word", word),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl Instruction {
+    /// Decodes a two byte instruction word.
+    pub fn decode(word: u16) -> Result<Instruction, DecodeError> {
+        let x: u8 = ((word & 0x0F00) >> 8) as u8;
+        let y: u8 = ((word & 0x00F0) >> 4) as u8;
+        let n: u8 = (word & 0x000F) as u8;
+        let byte: u8 = (word & 0x00FF) as u8;
+        let addr: u16 = word & 0x0FFF;
+        let instruction = match (word >> 12, x, y, n) {
+            (0x0, 0x0, 0xC, _) => Instruction::ScrollDown(n),
+            (0x0, 0x0, 0xD, _) => Instruction::ScrollUp(n),
+            (0x0, 0x0, 0xE, 0x0) => Instruction::Cls,
+            (0x0, 0x0, 0xE, 0xE) => Instruction::Ret,
+            (0x0, 0x0, 0xF, 0xB) => Instruction::ScrollRight,
+            (0x0, 0x0, 0xF, 0xC) => Instruction::ScrollLeft,
+            (0x0, 0x0, 0xF, 0xD) => Instruction::Exit,
+            (0x0, 0x0, 0xF, 0xE) => Instruction::Lores,
+            (0x0, 0x0, 0xF, 0xF) => Instruction::Hires,
+            (0x1, _, _, _) => Instruction::Jp(addr),
+            (0x2, _, _, _) => Instruction::Call(addr),
+            (0x3, _, _, _) => Instruction::SeByte { x, byte },
+            (0x4, _, _, _) => Instruction::SneByte { x, byte },
+            (0x5, _, _, 0x0) => Instruction::SeReg { x, y },
+            (0x5, _, _, 0x2) => Instruction::SaveRange { x, y },
+            (0x5, _, _, 0x3) => Instruction::LoadRange { x, y },
+            (0x6, _, _, _) => Instruction::LdByte { x, byte },
+            (0x7, _, _, _) => Instruction::AddByte { x, byte },
+            (0x8, _, _, 0x0) => Instruction::LdReg { x, y },
+            (0x8, _, _, 0x1) => Instruction::Or { x, y },
+            (0x8, _, _, 0x2) => Instruction::And { x, y },
+            (0x8, _, _, 0x3) => Instruction::Xor { x, y },
+            (0x8, _, _, 0x4) => Instruction::AddReg { x, y },
+            (0x8, _, _, 0x5) => Instruction::Sub { x, y },
+            (0x8, _, _, 0x6) => Instruction::Shr { x, y },
+            (0x8, _, _, 0x7) => Instruction::Subn { x, y },
+            (0x8, _, _, 0xE) => Instruction::Shl { x, y },
+            (0x9, _, _, 0x0) => Instruction::SneReg { x, y },
+            (0xA, _, _, _) => Instruction::LdI(addr),
+            (0xB, _, _, _) => Instruction::JpV0(addr),
+            (0xC, _, _, _) => Instruction::Rnd { x, byte },
+            (0xD, _, _, _) => Instruction::Drw { x, y, n },
+            (0xE, _, 0x9, 0xE) => Instruction::Skp(x),
+            (0xE, _, 0xA, 0x1) => Instruction::Sknp(x),
+            (0xF, 0x0, 0x0, 0x0) => return Err(DecodeError::NeedsOperand(word)),
+            (0xF, _, 0x0, 0x1) => Instruction::Plane(x),
+            (0xF, 0x0, 0x0, 0x2) => Instruction::Audio,
+            (0xF, _, 0x0, 0x7) => Instruction::LdVxDt(x),
+            (0xF, _, 0x0, 0xA) => Instruction::LdVxK(x),
+            (0xF, _, 0x1, 0x5) => Instruction::LdDtVx(x),
+            (0xF, _, 0x1, 0x8) => Instruction::LdStVx(x),
+            (0xF, _, 0x1, 0xE) => Instruction::AddI(x),
+            (0xF, _, 0x2, 0x9) => Instruction::LdF(x),
+            (0xF, _, 0x3, 0x0) => Instruction::LdHf(x),
+            (0xF, _, 0x3, 0x3) => Instruction::Bcd(x),
+            (0xF, _, 0x3, 0xA) => Instruction::Pitch(x),
+            (0xF, _, 0x5, 0x5) => Instruction::Store(x),
+            (0xF, _, 0x6, 0x5) => Instruction::Load(x),
+            (0xF, _, 0x7, 0x5) => Instruction::SaveFlags(x),
+            (0xF, _, 0x8, 0x5) => Instruction::LoadFlags(x),
+            _ => return Err(DecodeError::Unknown(word)),
+        };
+        Ok(instruction)
+    }
+
+    /// Decodes an instruction whose word may be followed by an operand word, as `F000 NNNN` is.
+    pub fn decode_long(word: u16, next: u16) -> Result<Instruction, DecodeError> {
+        match Self::decode(word) {
+            Err(DecodeError::NeedsOperand(_)) => Ok(Instruction::LdILong(next)),
+            other => other,
+        }
+    }
+
+    /// Decodes the instruction stored at `address`. Reading past the end of `mem` yields zeroes.
+    pub fn read(mem: &[u8], address: usize) -> Result<Instruction, DecodeError> {
+        let word_at = |at: usize| -> u16 {
+            let high = *mem.get(at).unwrap_or(&0) as u16;
+            let low = *mem.get(at + 1).unwrap_or(&0) as u16;
+            (high << 8) | low
+        };
+        Self::decode_long(word_at(address), word_at(address + 2))
+    }
+
+    /// The instruction word. For `LdILong` the address follows in a second word; see `to_bytes`.
+    pub fn encode(&self) -> u16 {
+        let xy = |opcode: u16, x: u8, y: u8, n: u16| opcode | ((x as u16) << 8) | ((y as u16) << 4) | n;
+        let xnn = |opcode: u16, x: u8, byte: u8| opcode | ((x as u16) << 8) | byte as u16;
+        match *self {
+            Instruction::ScrollDown(n) => 0x00C0 | n as u16,
+            Instruction::ScrollUp(n) => 0x00D0 | n as u16,
+            Instruction::Cls => 0x00E0,
+            Instruction::Ret => 0x00EE,
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::Lores => 0x00FE,
+            Instruction::Hires => 0x00FF,
+            Instruction::Jp(addr) => 0x1000 | addr,
+            Instruction::Call(addr) => 0x2000 | addr,
+            Instruction::SeByte { x, byte } => xnn(0x3000, x, byte),
+            Instruction::SneByte { x, byte } => xnn(0x4000, x, byte),
+            Instruction::SeReg { x, y } => xy(0x5000, x, y, 0x0),
+            Instruction::SaveRange { x, y } => xy(0x5000, x, y, 0x2),
+            Instruction::LoadRange { x, y } => xy(0x5000, x, y, 0x3),
+            Instruction::LdByte { x, byte } => xnn(0x6000, x, byte),
+            Instruction::AddByte { x, byte } => xnn(0x7000, x, byte),
+            Instruction::LdReg { x, y } => xy(0x8000, x, y, 0x0),
+            Instruction::Or { x, y } => xy(0x8000, x, y, 0x1),
+            Instruction::And { x, y } => xy(0x8000, x, y, 0x2),
+            Instruction::Xor { x, y } => xy(0x8000, x, y, 0x3),
+            Instruction::AddReg { x, y } => xy(0x8000, x, y, 0x4),
+            Instruction::Sub { x, y } => xy(0x8000, x, y, 0x5),
+            Instruction::Shr { x, y } => xy(0x8000, x, y, 0x6),
+            Instruction::Subn { x, y } => xy(0x8000, x, y, 0x7),
+            Instruction::Shl { x, y } => xy(0x8000, x, y, 0xE),
+            Instruction::SneReg { x, y } => xy(0x9000, x, y, 0x0),
+            Instruction::LdI(addr) => 0xA000 | addr,
+            Instruction::JpV0(addr) => 0xB000 | addr,
+            Instruction::Rnd { x, byte } => xnn(0xC000, x, byte),
+            Instruction::Drw { x, y, n } => xy(0xD000, x, y, n as u16),
+            Instruction::Skp(x) => xnn(0xE000, x, 0x9E),
+            Instruction::Sknp(x) => xnn(0xE000, x, 0xA1),
+            Instruction::LdILong(_) => 0xF000,
+            Instruction::Plane(mask) => xnn(0xF000, mask, 0x01),
+            Instruction::Audio => 0xF002,
+            Instruction::LdVxDt(x) => xnn(0xF000, x, 0x07),
+            Instruction::LdVxK(x) => xnn(0xF000, x, 0x0A),
+            Instruction::LdDtVx(x) => xnn(0xF000, x, 0x15),
+            Instruction::LdStVx(x) => xnn(0xF000, x, 0x18),
+            Instruction::AddI(x) => xnn(0xF000, x, 0x1E),
+            Instruction::LdF(x) => xnn(0xF000, x, 0x29),
+            Instruction::LdHf(x) => xnn(0xF000, x, 0x30),
+            Instruction::Bcd(x) => xnn(0xF000, x, 0x33),
+            Instruction::Pitch(x) => xnn(0xF000, x, 0x3A),
+            Instruction::Store(x) => xnn(0xF000, x, 0x55),
+            Instruction::Load(x) => xnn(0xF000, x, 0x65),
+            Instruction::SaveFlags(x) => xnn(0xF000, x, 0x75),
+            Instruction::LoadFlags(x) => xnn(0xF000, x, 0x85),
+        }
+    }
+
+    /// The instruction as it is stored in memory, big-endian.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let word = self.encode();
+        let mut bytes = vec![(word >> 8) as u8, word as u8];
+        if let Instruction::LdILong(addr) = *self {
+            bytes.push((addr >> 8) as u8);
+            bytes.push(addr as u8);
+        }
+        bytes
+    }
+
+    /// Size of the instruction in bytes.
+    pub fn size(&self) -> u16 {
+        match self {
+            Instruction::LdILong(_) => 4,
+            _ => 2,
+        }
+    }
+
+    /// The earliest platform that implements this instruction.
+    pub fn platform(&self) -> Platform {
+        match self {
+            Instruction::ScrollDown(_) | Instruction::ScrollRight | Instruction::ScrollLeft
+            | Instruction::Exit | Instruction::Lores | Instruction::Hires
+            | Instruction::LdHf(_) | Instruction::SaveFlags(_) | Instruction::LoadFlags(_) => Platform::SuperChip,
+            Instruction::ScrollUp(_) | Instruction::SaveRange { .. } | Instruction::LoadRange { .. }
+            | Instruction::LdILong(_) | Instruction::Plane(_) | Instruction::Audio
+            | Instruction::Pitch(_) => Platform::XoChip,
+            _ => Platform::Chip8,
+        }
+    }
+
+    /// Whether executing this instruction may skip the one after it.
+    pub fn is_skip(&self) -> bool {
+        matches!(self, Instruction::SeByte { .. } | Instruction::SneByte { .. } | Instruction::SeReg { .. }
+            | Instruction::SneReg { .. } | Instruction::Skp(_) | Instruction::Sknp(_))
+    }
+}
+
+struct Reg(u8);
+
+impl fmt::Display for Reg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "v{:x}", self.0)
+    }
+}
+
+/// Octo assembly syntax. Skips are rendered as the `if ... then` that compiles to them, so the
+/// condition reads as the one under which the following instruction runs.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Instruction::ScrollDown(n) => write!(f, "scroll-down {}", n),
+            Instruction::ScrollUp(n) => write!(f, "scroll-up {}", n),
+            Instruction::Cls => write!(f, "clear"),
+            Instruction::Ret => write!(f, "return"),
+            Instruction::ScrollRight => write!(f, "scroll-right"),
+            Instruction::ScrollLeft => write!(f, "scroll-left"),
+            Instruction::Exit => write!(f, "exit"),
+            Instruction::Lores => write!(f, "lores"),
+            Instruction::Hires => write!(f, "hires"),
+            Instruction::Jp(addr) => write!(f, "jump 0x{:03X}", addr),
+            Instruction::Call(addr) => write!(f, ":call 0x{:03X}", addr),
+            Instruction::SeByte { x, byte } => write!(f, "if {} != 0x{:02X} then", Reg(x), byte),
+            Instruction::SneByte { x, byte } => write!(f, "if {} == 0x{:02X} then", Reg(x), byte),
+            Instruction::SeReg { x, y } => write!(f, "if {} != {} then", Reg(x), Reg(y)),
+            Instruction::SaveRange { x, y } => write!(f, "save {} - {}", Reg(x), Reg(y)),
+            Instruction::LoadRange { x, y } => write!(f, "load {} - {}", Reg(x), Reg(y)),
+            Instruction::LdByte { x, byte } => write!(f, "{} := 0x{:02X}", Reg(x), byte),
+            Instruction::AddByte { x, byte } => write!(f, "{} += 0x{:02X}", Reg(x), byte),
+            Instruction::LdReg { x, y } => write!(f, "{} := {}", Reg(x), Reg(y)),
+            Instruction::Or { x, y } => write!(f, "{} |= {}", Reg(x), Reg(y)),
+            Instruction::And { x, y } => write!(f, "{} &= {}", Reg(x), Reg(y)),
+            Instruction::Xor { x, y } => write!(f, "{} ^= {}", Reg(x), Reg(y)),
+            Instruction::AddReg { x, y } => write!(f, "{} += {}", Reg(x), Reg(y)),
+            Instruction::Sub { x, y } => write!(f, "{} -= {}", Reg(x), Reg(y)),
+            Instruction::Shr { x, y } => write!(f, "{} >>= {}", Reg(x), Reg(y)),
+            Instruction::Subn { x, y } => write!(f, "{} =- {}", Reg(x), Reg(y)),
+            Instruction::Shl { x, y } => write!(f, "{} <<= {}", Reg(x), Reg(y)),
+            Instruction::SneReg { x, y } => write!(f, "if {} == {} then", Reg(x), Reg(y)),
+            Instruction::LdI(addr) => write!(f, "i := 0x{:03X}", addr),
+            Instruction::JpV0(addr) => write!(f, "jump0 0x{:03X}", addr),
+            Instruction::Rnd { x, byte } => write!(f, "{} := random 0x{:02X}", Reg(x), byte),
+            Instruction::Drw { x, y, n } => write!(f, "sprite {} {} {}", Reg(x), Reg(y), n),
+            Instruction::Skp(x) => write!(f, "if {} -key then", Reg(x)),
+            Instruction::Sknp(x) => write!(f, "if {} key then", Reg(x)),
+            Instruction::LdILong(addr) => write!(f, "i := long 0x{:04X}", addr),
+            Instruction::Plane(mask) => write!(f, "plane {}", mask),
+            Instruction::Audio => write!(f, "audio"),
+            Instruction::LdVxDt(x) => write!(f, "{} := delay", Reg(x)),
+            Instruction::LdVxK(x) => write!(f, "{} := key", Reg(x)),
+            Instruction::LdDtVx(x) => write!(f, "delay := {}", Reg(x)),
+            Instruction::LdStVx(x) => write!(f, "buzzer := {}", Reg(x)),
+            Instruction::AddI(x) => write!(f, "i += {}", Reg(x)),
+            Instruction::LdF(x) => write!(f, "i := hex {}", Reg(x)),
+            Instruction::LdHf(x) => write!(f, "i := bighex {}", Reg(x)),
+            Instruction::Bcd(x) => write!(f, "bcd {}", Reg(x)),
+            Instruction::Pitch(x) => write!(f, "pitch := {}", Reg(x)),
+            Instruction::Store(x) => write!(f, "save {}", Reg(x)),
+            Instruction::Load(x) => write!(f, "load {}", Reg(x)),
+            Instruction::SaveFlags(x) => write!(f, "saveflags {}", Reg(x)),
+            Instruction::LoadFlags(x) => write!(f, "loadflags {}", Reg(x)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_word_round_trips_on_every_platform() {
+        for &platform in Platform::ALL.iter() {
+            let mut implemented = 0;
+            for word in 0x0000..=0xFFFF_u16 {
+                let instruction = match Instruction::decode(word) {
+                    Ok(instruction) => instruction,
+                    Err(DecodeError::NeedsOperand(found)) => {
+                        assert_eq!(found, 0xF000);
+                        continue;
+                    },
+                    Err(DecodeError::Unknown(found)) => {
+                        assert_eq!(found, word);
+                        continue;
+                    },
+                };
+                if instruction.platform() > platform {
+                    continue;
+                }
+                implemented += 1;
+                let bytes = instruction.to_bytes();
+                assert_eq!(instruction.encode(), word, "{:?}", instruction);
+                assert_eq!(bytes, word.to_be_bytes(), "{:?}", instruction);
+                assert_eq!(instruction.size(), 2);
+                assert_eq!(Instruction::read(&bytes, 0), Ok(instruction));
+            }
+            assert!(implemented > 0, "{} implements nothing", platform);
+        }
+    }
+
+    #[test]
+    fn long_load_round_trips_every_address() {
+        for addr in 0x0000..=0xFFFF_u16 {
+            let instruction = Instruction::decode_long(0xF000, addr).unwrap();
+            assert_eq!(instruction, Instruction::LdILong(addr));
+            assert_eq!(instruction.encode(), 0xF000);
+            assert_eq!(instruction.size(), 4);
+            let bytes = instruction.to_bytes();
+            assert_eq!(bytes, [0xF0, 0x00, (addr >> 8) as u8, addr as u8]);
+            assert_eq!(Instruction::read(&bytes, 0), Ok(instruction));
+        }
+        assert_eq!(Instruction::LdILong(0).platform(), Platform::XoChip);
+    }
+
+    #[test]
+    fn later_platforms_only_add_instructions() {
+        let counts: Vec<usize> = Platform::ALL.iter().map(|&platform| {
+            (0x0000..=0xFFFF_u16)
+                .filter(|&word| matches!(Instruction::decode(word), Ok(instruction) if instruction.platform() <= platform))
+                .count()
+        }).collect();
+        assert!(counts.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", counts);
+    }
+
+    #[test]
+    fn unknown_words_are_reported() {
+        for &word in [0x0000, 0x00E1, 0x5001, 0x800F, 0x9001, 0xE000, 0xF0FF].iter() {
+            assert_eq!(Instruction::decode(word), Err(DecodeError::Unknown(word)));
+        }
+        assert_eq!(Instruction::decode(0xF000), Err(DecodeError::NeedsOperand(0xF000)));
+    }
+
+    #[test]
+    fn displays_octo_mnemonics() {
+        let cases = [
+            (0x00E0, "clear"),
+            (0x00EE, "return"),
+            (0x00C4, "scroll-down 4"),
+            (0x1234, "jump 0x234"),
+            (0x2ABC, ":call 0xABC"),
+            (0x3A05, "if va != 0x05 then"),
+            (0x4B10, "if vb == 0x10 then"),
+            (0x5120, "if v1 != v2 then"),
+            (0x5122, "save v1 - v2"),
+            (0x6FFF, "vf := 0xFF"),
+            (0x8016, "v0 >>= v1"),
+            (0x8017, "v0 =- v1"),
+            (0xA123, "i := 0x123"),
+            (0xB200, "jump0 0x200"),
+            (0xC30F, "v3 := random 0x0F"),
+            (0xD125, "sprite v1 v2 5"),
+            (0xE59E, "if v5 -key then"),
+            (0xE5A1, "if v5 key then"),
+            (0xF301, "plane 3"),
+            (0xF40A, "v4 := key"),
+            (0xF633, "bcd v6"),
+            (0xF730, "i := bighex v7"),
+            (0xF855, "save v8"),
+            (0xF965, "load v9"),
+        ];
+        for &(word, text) in cases.iter() {
+            assert_eq!(Instruction::decode(word).unwrap().to_string(), text, "{:04X}", word);
+        }
+        assert_eq!(Instruction::LdILong(0x1234).to_string(), "i := long 0x1234");
+    }
+}
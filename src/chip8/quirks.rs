@@ -0,0 +1,103 @@
+use std::fmt;
+
+/// Behaviours that differ between CHIP-8 interpreters. Every flag is `false` for the behaviour
+/// the interpreter had before quirks became configurable, which is what `Default` gives.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6/8XYE shift VY and store the result in VX, rather than shifting VX in place.
+    pub shift_reads_vy: bool,
+    /// FX55/FX65 leave I pointing just past the last register transferred.
+    pub load_store_increments_i: bool,
+    /// BNNN is decoded as BXNN and jumps to XNN + VX instead of NNN + V0.
+    pub jump_uses_vx: bool,
+    /// 8XY1, 8XY2 and 8XY3 clear VF.
+    pub logic_resets_vf: bool,
+    /// DXYN wraps sprites around the screen edges instead of clipping them.
+    pub wrap_sprites: bool,
+}
+
+/// Named quirk sets matching the interpreters most ROMs were written for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuirksPreset {
+    CosmacVip,
+    Chip48,
+    SuperChip,
+    XoChip,
+}
+
+impl Quirks {
+    pub fn cosmac_vip() -> Self {
+        Self {
+            shift_reads_vy: true,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            logic_resets_vf: true,
+            wrap_sprites: false,
+        }
+    }
+
+    pub fn chip48() -> Self {
+        Self {
+            shift_reads_vy: false,
+            load_store_increments_i: true,
+            jump_uses_vx: true,
+            logic_resets_vf: false,
+            wrap_sprites: false,
+        }
+    }
+
+    pub fn superchip() -> Self {
+        Self {
+            shift_reads_vy: false,
+            load_store_increments_i: false,
+            jump_uses_vx: true,
+            logic_resets_vf: false,
+            wrap_sprites: false,
+        }
+    }
+
+    pub fn xochip() -> Self {
+        Self {
+            shift_reads_vy: true,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            logic_resets_vf: false,
+            wrap_sprites: true,
+        }
+    }
+}
+
+impl QuirksPreset {
+    pub const ALL: [QuirksPreset; 4] = [
+        QuirksPreset::CosmacVip,
+        QuirksPreset::Chip48,
+        QuirksPreset::SuperChip,
+        QuirksPreset::XoChip,
+    ];
+
+    pub fn quirks(&self) -> Quirks {
+        match self {
+            QuirksPreset::CosmacVip => Quirks::cosmac_vip(),
+            QuirksPreset::Chip48 => Quirks::chip48(),
+            QuirksPreset::SuperChip => Quirks::superchip(),
+            QuirksPreset::XoChip => Quirks::xochip(),
+        }
+    }
+
+    /// The preset whose flags exactly match `quirks`, if any.
+    pub fn matching(quirks: &Quirks) -> Option<QuirksPreset> {
+        Self::ALL.iter().cloned().find(|preset| preset.quirks() == *quirks)
+    }
+}
+
+impl fmt::Display for QuirksPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            QuirksPreset::CosmacVip => "COSMAC VIP",
+            QuirksPreset::Chip48 => "CHIP-48",
+            QuirksPreset::SuperChip => "SUPER-CHIP",
+            QuirksPreset::XoChip => "XO-CHIP",
+        };
+        write!(f, "{}", name)
+    }
+}
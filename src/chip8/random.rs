@@ -0,0 +1,44 @@
+/// Seed used by `Config::new`, so that runs are reproducible unless the host picks another one.
+pub const DEFAULT_SEED: u64 = 0x5EED_C8C8_5EED_C8C8;
+
+/// Where CXNN gets its random bytes from. The whole generator state must fit in a `u64` so it
+/// can be saved and restored along with the rest of the emulator state.
+pub trait RandomSource: Send {
+    fn next_byte(&mut self) -> u8;
+    fn state(&self) -> u64;
+    fn restore(&mut self, state: u64);
+}
+
+/// The default `RandomSource`, a xorshift64* generator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct XorShift {
+    state: u64,
+}
+
+impl XorShift {
+    pub fn new(seed: u64) -> Self {
+        //Spread the seed with a SplitMix64 step; xorshift must never be seeded with zero
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        Self { state: if z == 0 { DEFAULT_SEED } else { z } }
+    }
+}
+
+impl RandomSource for XorShift {
+    fn next_byte(&mut self) -> u8 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+    }
+
+    fn state(&self) -> u64 {
+        self.state
+    }
+
+    fn restore(&mut self, state: u64) {
+        self.state = if state == 0 { DEFAULT_SEED } else { state };
+    }
+}
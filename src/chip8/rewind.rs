@@ -0,0 +1,317 @@
+use std::collections::VecDeque;
+
+use super::{Chip8, StateError};
+
+/// A bounded history of save states for stepping a running program backwards.
+///
+/// Only the newest snapshot is kept whole. Every older one is stored as the difference to the
+/// snapshot after it: the two states are XORed, so bytes that did not change become zero, and
+/// the runs of zeros are collapsed. Most of memory and the framebuffer is untouched from one
+/// snapshot to the next, which keeps each step down to a few dozen bytes for typical games.
+/// When the history outgrows its byte budget the oldest steps are dropped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RewindBuffer {
+    budget: usize,
+    interval: u32,
+    frames: u32,
+    latest: Option<Vec<u8>>,
+    steps: VecDeque<Vec<u8>>,
+    used: usize,
+}
+
+impl RewindBuffer {
+    /// A buffer holding at most `budget` bytes that snapshots every `interval` frames.
+    pub fn new(budget: usize, interval: u32) -> Self {
+        Self {
+            budget,
+            interval: interval.max(1),
+            frames: 0,
+            latest: None,
+            steps: VecDeque::new(),
+            used: 0,
+        }
+    }
+
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.evict();
+    }
+
+    /// Bytes currently held, including the newest full snapshot.
+    pub fn memory_used(&self) -> usize {
+        self.used
+    }
+
+    /// How many times `rewind` can step back.
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames = 0;
+        self.latest = None;
+        self.steps.clear();
+        self.used = 0;
+    }
+
+    /// Call once per emulated frame; every `interval`th call takes a snapshot of `chip8`.
+    pub fn record(&mut self, chip8: &Chip8) {
+        if self.budget == 0 {
+            return;
+        }
+        self.frames += 1;
+        if self.frames < self.interval {
+            return;
+        }
+        self.frames = 0;
+        let state = chip8.save_state();
+        if let Some(latest) = self.latest.take() {
+            let step = delta(&state, &latest);
+            self.used += step.len();
+            self.used -= latest.len();
+            self.steps.push_back(step);
+        }
+        self.used += state.len();
+        self.latest = Some(state);
+        self.evict();
+    }
+
+    /// Restores `chip8` to the snapshot before the newest one and forgets the newest. Returns
+    /// `false`, leaving `chip8` untouched, once the history is exhausted.
+    pub fn rewind(&mut self, chip8: &mut Chip8) -> Result<bool, StateError> {
+        let (step, latest) = match (self.steps.pop_back(), self.latest.take()) {
+            (Some(step), Some(latest)) => (step, latest),
+            (_, latest) => {
+                self.latest = latest;
+                return Ok(false);
+            },
+        };
+        let state = apply(&latest, &step);
+        self.used = self.used + state.len() - latest.len() - step.len();
+        self.frames = 0;
+        let result = chip8.load_state(&state);
+        self.latest = Some(state);
+        result.map(|_| true)
+    }
+
+    fn evict(&mut self) {
+        while self.used > self.budget {
+            match self.steps.pop_front() {
+                Some(step) => self.used -= step.len(),
+                None => {
+                    if let Some(latest) = self.latest.take() {
+                        self.used -= latest.len();
+                    }
+                    break;
+                },
+            }
+        }
+    }
+}
+
+/// Encodes how to turn `from` into `to` as the length of `to` followed by (zero run, literal
+/// run, literal bytes) records over `from XOR to`, with lengths as LEB128 varints.
+fn delta(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_varint(&mut out, to.len());
+    let len = from.len().max(to.len());
+    let xor = |i: usize| from.get(i).copied().unwrap_or(0) ^ to.get(i).copied().unwrap_or(0);
+    let mut pos = 0;
+    while pos < len {
+        let zeros_start = pos;
+        while pos < len && xor(pos) == 0 {
+            pos += 1;
+        }
+        if pos == len {
+            break;
+        }
+        let literal_start = pos;
+        while pos < len && xor(pos) != 0 {
+            pos += 1;
+        }
+        write_varint(&mut out, literal_start - zeros_start);
+        write_varint(&mut out, pos - literal_start);
+        out.extend((literal_start..pos).map(xor));
+    }
+    out
+}
+
+fn apply(from: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut pos = 0;
+    let len = read_varint(delta, &mut pos);
+    let mut to = from.to_vec();
+    to.resize(from.len().max(len), 0);
+    let mut target = 0;
+    while pos < delta.len() {
+        target += read_varint(delta, &mut pos);
+        let literals = read_varint(delta, &mut pos);
+        for byte in &delta[pos..pos + literals] {
+            to[target] ^= byte;
+            target += 1;
+        }
+        pos += literals;
+    }
+    to.truncate(len);
+    to
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7F) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Config;
+
+    /// Counts V0 up forever, so every frame leaves a different state.
+    const ROM: [u8; 4] = [0x70, 0x01, 0x12, 0x00];
+
+    fn chip8() -> Chip8 {
+        Chip8::new(&ROM, Config::default()).unwrap()
+    }
+
+    fn step(chip8: &mut Chip8) {
+        for _ in 0..3 {
+            chip8.tick([false; 16]).unwrap();
+        }
+    }
+
+    #[test]
+    fn varints_round_trip() {
+        for &value in [0, 1, 0x7F, 0x80, 300, 0x3FFF, 0x4000, u32::MAX as usize, usize::MAX].iter() {
+            let mut out = Vec::new();
+            write_varint(&mut out, value);
+            let mut pos = 0;
+            assert_eq!(read_varint(&out, &mut pos), value);
+            assert_eq!(pos, out.len());
+        }
+        let mut out = Vec::new();
+        write_varint(&mut out, 300);
+        assert_eq!(out, [0xAC, 0x02]);
+    }
+
+    #[test]
+    fn delta_round_trips() {
+        let long_run: Vec<u8> = (0..1000).map(|i| (i % 7) as u8).collect();
+        let mut sparse = long_run.clone();
+        sparse[0] ^= 1;
+        sparse[500] ^= 0xFF;
+        sparse[999] ^= 0x80;
+        let cases: [(&[u8], &[u8]); 7] = [
+            (&[], &[]),
+            (&[1, 2, 3], &[1, 2, 3]),
+            (&[1, 2, 3], &[3, 2, 1]),
+            (&[1, 2, 3], &[1, 2, 3, 4, 5]),
+            (&[1, 2, 3, 4, 5], &[1, 9]),
+            (&long_run, &sparse),
+            (&sparse, &[]),
+        ];
+        for &(from, to) in cases.iter() {
+            assert_eq!(apply(from, &delta(from, to)), to, "{:?} -> {:?}", from, to);
+        }
+    }
+
+    #[test]
+    fn delta_stores_only_changed_runs() {
+        let from = vec![0x55; 4096];
+        assert_eq!(delta(&from, &from), [0x80, 0x20]);
+        let mut to = from.clone();
+        to[200] = 0xAA;
+        to[201] = 0x54;
+        //Length, then 200 unchanged bytes, then two literal XORed bytes
+        assert_eq!(delta(&from, &to), [0x80, 0x20, 0xC8, 0x01, 0x02, 0xFF, 0x01]);
+    }
+
+    #[test]
+    fn rewinds_through_recorded_states() {
+        let mut chip8 = chip8();
+        let mut buffer = RewindBuffer::new(1 << 20, 1);
+        let mut states = Vec::new();
+        for _ in 0..10 {
+            step(&mut chip8);
+            buffer.record(&chip8);
+            states.push(chip8.save_state());
+        }
+        assert_eq!(buffer.len(), 9);
+        states.pop();
+        while let Some(expected) = states.pop() {
+            assert_eq!(buffer.rewind(&mut chip8), Ok(true));
+            assert_eq!(chip8.save_state(), expected);
+        }
+        assert_eq!(buffer.rewind(&mut chip8), Ok(false));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn snapshots_every_interval() {
+        let mut chip8 = chip8();
+        let mut buffer = RewindBuffer::new(1 << 20, 3);
+        for _ in 0..9 {
+            step(&mut chip8);
+            buffer.record(&chip8);
+        }
+        assert_eq!(buffer.len(), 2);
+    }
+
+    #[test]
+    fn evicts_the_oldest_steps_to_stay_within_the_budget() {
+        let mut chip8 = chip8();
+        let full = chip8.save_state().len();
+        let mut buffer = RewindBuffer::new(1 << 20, 1);
+        for _ in 0..20 {
+            step(&mut chip8);
+            buffer.record(&chip8);
+        }
+        assert_eq!(buffer.len(), 19);
+        let used = buffer.memory_used();
+        assert!(used > full && used < full * 2, "{} bytes for 19 small steps", used);
+
+        buffer.set_budget(full + (used - full) / 2);
+        assert!(buffer.memory_used() <= full + (used - full) / 2);
+        assert!(!buffer.is_empty() && buffer.len() < 19, "{} steps left", buffer.len());
+        //What is left still rewinds to the newest states
+        let newest = chip8.save_state();
+        step(&mut chip8);
+        buffer.record(&chip8);
+        assert_eq!(buffer.rewind(&mut chip8), Ok(true));
+        assert_eq!(chip8.save_state(), newest);
+
+        buffer.set_budget(full - 1);
+        assert_eq!((buffer.len(), buffer.memory_used()), (0, 0));
+        assert_eq!(buffer.rewind(&mut chip8), Ok(false));
+    }
+
+    #[test]
+    fn zero_budget_records_nothing() {
+        let mut chip8 = chip8();
+        let mut buffer = RewindBuffer::new(0, 1);
+        for _ in 0..5 {
+            step(&mut chip8);
+            buffer.record(&chip8);
+        }
+        assert_eq!((buffer.len(), buffer.memory_used()), (0, 0));
+    }
+}
@@ -0,0 +1,197 @@
+use super::timing::{self, TimingMode};
+use super::{Chip8, Chip8Error, CpuStatus, Instruction, RunTarget};
+
+/// Rate at which the delay and sound timers count down and the display refreshes.
+pub const FRAME_RATE: u32 = 60;
+
+/// Decides how many instructions each 60 Hz frame runs.
+///
+/// In `Fast` mode the clock speed is split into whole instructions per frame and the fractional
+/// remainder is carried into the following frame, so e.g. 800 Hz alternates between 13 and 14
+/// instructions and averages out to exactly 800 per second. In `CosmacVip` mode the frame is a
+/// budget of 1802 machine cycles instead, and any overrun is paid back in the next frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameScheduler {
+    timing: TimingMode,
+    instructions_per_frame: f64,
+    carry: f64,
+    cycle_carry: i64,
+}
+
+impl FrameScheduler {
+    pub fn new(clock_speed: u32) -> Self {
+        Self {
+            timing: TimingMode::Fast,
+            instructions_per_frame: clock_speed as f64 / FRAME_RATE as f64,
+            carry: 0.0,
+            cycle_carry: 0,
+        }
+    }
+
+    pub fn set_clock_speed(&mut self, clock_speed: u32) {
+        self.instructions_per_frame = clock_speed as f64 / FRAME_RATE as f64;
+    }
+
+    pub fn instructions_per_frame(&self) -> f64 {
+        self.instructions_per_frame
+    }
+
+    pub fn timing(&self) -> TimingMode {
+        self.timing
+    }
+
+    pub fn set_timing(&mut self, timing: TimingMode) {
+        self.timing = timing;
+        self.carry = 0.0;
+        self.cycle_carry = 0;
+    }
+
+    /// How many instructions the next `Fast` frame should execute.
+    pub fn next_frame(&mut self) -> u32 {
+        let budget = self.instructions_per_frame + self.carry;
+        let whole = budget.floor();
+        self.carry = budget - whole;
+        whole as u32
+    }
+
+    /// Runs one frame: the frame's share of instructions, then a single timer decrement standing
+    /// in for the vertical blank interrupt. Execution stops early if the interpreter leaves the
+    /// `Running` state; while FX0A waits for a key the keypad is polled and the timers still run.
+    /// Returns the number of instructions run.
+    pub fn run_frame(&mut self, chip8: &mut Chip8, kb_state: [bool; 16]) -> Result<u32, Chip8Error> {
+        self.run_frame_until(chip8, kb_state, None)
+    }
+
+    /// Like `run_frame`, but stops as soon as an instruction reaches `target`. The timers only
+    /// count down if the whole frame ran, so a debugger run is paced like normal execution.
+    pub fn run_frame_until(&mut self, chip8: &mut Chip8, kb_state: [bool; 16], target: Option<RunTarget>) -> Result<u32, Chip8Error> {
+        self.run(chip8, kb_state, target, u32::MAX).map(|(executed, _)| executed)
+    }
+
+    /// Like `run_frame`, but executes at most `limit` instructions, e.g. to stop a headless run
+    /// after an exact instruction count. Returns the number of instructions run and whether the
+    /// frame completed; the timers only count down if it did.
+    pub fn run_frame_limited(&mut self, chip8: &mut Chip8, kb_state: [bool; 16], limit: u32) -> Result<(u32, bool), Chip8Error> {
+        self.run(chip8, kb_state, None, limit)
+    }
+
+    fn run(&mut self, chip8: &mut Chip8, kb_state: [bool; 16], target: Option<RunTarget>, limit: u32) -> Result<(u32, bool), Chip8Error> {
+        let (executed, complete) = match self.timing {
+            TimingMode::Fast => self.run_fast(chip8, kb_state, target, limit)?,
+            TimingMode::CosmacVip => self.run_vip(chip8, kb_state, target, limit)?,
+        };
+        let complete = complete && !matches!(target, Some(target) if target.reached(chip8));
+        if complete && (chip8.status == CpuStatus::Running || chip8.status.is_waiting_for_key()) {
+            chip8.decrement_counters();
+        }
+        Ok((executed, complete))
+    }
+
+    fn run_fast(&mut self, chip8: &mut Chip8, kb_state: [bool; 16], target: Option<RunTarget>, limit: u32) -> Result<(u32, bool), Chip8Error> {
+        let budget = self.next_frame();
+        let mut executed = 0;
+        while executed < budget {
+            if executed == limit {
+                return Ok((executed, false));
+            }
+            if !Self::ready(chip8, kb_state)? {
+                break;
+            }
+            chip8.tick(kb_state)?;
+            executed += 1;
+            if matches!(target, Some(target) if target.reached(chip8)) {
+                return Ok((executed, false));
+            }
+        }
+        Ok((executed, true))
+    }
+
+    /// Lets an interpreter blocked in FX0A look at the keypad. Returns whether it may execute.
+    fn ready(chip8: &mut Chip8, kb_state: [bool; 16]) -> Result<bool, Chip8Error> {
+        if chip8.status.is_waiting_for_key() {
+            chip8.tick(kb_state)?;
+        }
+        Ok(chip8.status == CpuStatus::Running)
+    }
+
+    fn run_vip(&mut self, chip8: &mut Chip8, kb_state: [bool; 16], target: Option<RunTarget>, limit: u32) -> Result<(u32, bool), Chip8Error> {
+        let mut budget = timing::VIP_CYCLES_PER_FRAME - timing::VIP_INTERRUPT_CYCLES + self.cycle_carry;
+        let mut executed = 0;
+        self.cycle_carry = 0;
+        while budget > 0 {
+            if executed == limit {
+                return Ok((executed, false));
+            }
+            if !Self::ready(chip8, kb_state)? {
+                break;
+            }
+            //Undecodable instructions still cost something; tick reports the actual error
+            let instruction = chip8.current_instruction();
+            let cost = match &instruction {
+                Ok(instruction) => timing::vip_cycles(instruction),
+                Err(_) => 0,
+            };
+            chip8.tick(kb_state)?;
+            executed += 1;
+            budget -= cost as i64;
+            if matches!(target, Some(target) if target.reached(chip8)) {
+                return Ok((executed, false));
+            }
+            //Display wait: the interpreter idles until the next interrupt after drawing
+            if let Ok(Instruction::Drw { .. }) = instruction {
+                return Ok((executed, true));
+            }
+        }
+        self.cycle_carry = budget.min(0);
+        Ok((executed, true))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Config;
+
+    /// v0 := 60, delay := v0, then counts v1 up forever.
+    const ROM: [u8; 8] = [0x60, 0x3C, 0xF0, 0x15, 0x71, 0x01, 0x12, 0x04];
+
+    fn chip8() -> Chip8 {
+        Chip8::new(&ROM, Config::default()).unwrap()
+    }
+
+    #[test]
+    fn limited_frames_stop_early_without_counting_down() {
+        let mut chip8 = chip8();
+        let mut scheduler = FrameScheduler::new(600);
+        assert_eq!(scheduler.run_frame_limited(&mut chip8, [false; 16], 4), Ok((4, false)));
+        assert_eq!(chip8.delay_timer(), 60);
+        assert_eq!(scheduler.run_frame_limited(&mut chip8, [false; 16], 10), Ok((10, true)));
+        assert_eq!(chip8.delay_timer(), 59);
+        assert_eq!(scheduler.run_frame_limited(&mut chip8, [false; 16], u32::MAX), Ok((10, true)));
+        assert_eq!(chip8.delay_timer(), 58);
+    }
+
+    #[test]
+    fn unlimited_frames_match_run_frame() {
+        let (mut limited, mut plain) = (chip8(), chip8());
+        let (mut a, mut b) = (FrameScheduler::new(800), FrameScheduler::new(800));
+        for _ in 0..30 {
+            let (executed, complete) = a.run_frame_limited(&mut limited, [false; 16], u32::MAX).unwrap();
+            assert!(complete);
+            assert_eq!(b.run_frame(&mut plain, [false; 16]), Ok(executed));
+        }
+        assert_eq!(limited.save_state(), plain.save_state());
+    }
+
+    #[test]
+    fn limits_apply_in_vip_timing() {
+        let mut chip8 = chip8();
+        let mut scheduler = FrameScheduler::new(600);
+        scheduler.set_timing(TimingMode::CosmacVip);
+        assert_eq!(scheduler.run_frame_limited(&mut chip8, [false; 16], 3), Ok((3, false)));
+        assert_eq!(chip8.delay_timer(), 60);
+        let (_, complete) = scheduler.run_frame_limited(&mut chip8, [false; 16], u32::MAX).unwrap();
+        assert!(complete);
+        assert_eq!(chip8.delay_timer(), 59);
+    }
+}
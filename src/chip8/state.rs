@@ -0,0 +1,345 @@
+//! The on-disk save state format.
+//!
+//! A state starts with the `MAGIC` bytes and a little-endian `u16` format version, followed by
+//! every field of the machine in a fixed order. Versions only ever grow: a build rejects any
+//! state with a version above `VERSION` instead of guessing at fields it does not know about.
+
+use super::{constants, Chip8, CpuStatus, DecodeCache, Display, Platform, StateError};
+
+pub const MAGIC: [u8; 4] = *b"TJR8";
+/// Version 2 widened the stack depth and stack pointer to 16 bits for configurable stacks.
+pub const VERSION: u16 = 2;
+
+impl Chip8 {
+    /// Serialises the complete machine state: memory, registers, stack, timers, keypad latch,
+    /// framebuffer, status and random number generator state. The `Config` is not included;
+    /// a state can only be loaded into an interpreter built for the same platform. Pausing is up
+    /// to the host, so a paused interpreter is saved with the status it will resume to.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = Writer(Vec::with_capacity(self.bus.len() + self.screen.pixels().len() + 128));
+        w.bytes(&MAGIC);
+        w.u16(VERSION);
+        w.u8(platform_id(self.config.platform));
+        w.u32(self.bus.len() as u32);
+        w.bytes(self.bus.memory());
+        w.bytes(&self.regs);
+        w.u16(self.pc);
+        w.u16(self.idx);
+        w.u16(self.op_address);
+        w.u16(self.stack.len() as u16);
+        for entry in self.stack.iter() {
+            w.u16(*entry);
+        }
+        w.u16(self.sp as u16);
+        w.u8(self.delay);
+        w.u8(self.sound);
+        for key in self.keyboard.iter() {
+            w.u8(*key as u8);
+        }
+        w.u8(self.screen.is_hires() as u8);
+        w.bytes(self.screen.pixels());
+        w.bytes(&self.rpl);
+        w.u8(self.planes);
+        w.bytes(&self.audio_pattern);
+        w.u8(self.pitch);
+        let status = if self.status == CpuStatus::Paused { self.resume_status } else { self.status };
+        match status {
+            CpuStatus::Running | CpuStatus::Paused => w.u8(0),
+            CpuStatus::AwaitingKeyPress { x } => { w.u8(1); w.u8(x); },
+            CpuStatus::AwaitingKeyRelease { x, key } => { w.u8(2); w.u8(x); w.u8(key); },
+            CpuStatus::Halted => w.u8(3),
+            CpuStatus::Exited => w.u8(4),
+        }
+        w.u64(self.rng.state());
+        w.0
+    }
+
+    /// Restores a state written by `save_state`. Nothing is changed unless the whole state is
+    /// valid for this interpreter. A paused interpreter stays paused.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut r = Reader { data, pos: 0 };
+        if r.bytes(MAGIC.len()).map_err(|_| StateError::NotASaveState)? != MAGIC {
+            return Err(StateError::NotASaveState);
+        }
+        let version = r.u16()?;
+        if version > VERSION {
+            return Err(StateError::UnsupportedVersion { version, supported: VERSION });
+        }
+        if r.u8()? != platform_id(self.config.platform) || r.u32()? as usize != self.bus.len() {
+            return Err(StateError::MachineMismatch);
+        }
+        let mem = r.bytes(self.bus.len())?.to_vec();
+        let mut regs = self.regs;
+        regs.copy_from_slice(r.bytes(constants::NUM_REGISTERS)?);
+        let pc = r.u16()?;
+        let idx = r.u16()?;
+        let op_address = r.u16()?;
+        let depth = if version == 1 { r.u8()? as usize } else { r.u16()? as usize };
+        if depth != self.stack.len() {
+            return Err(StateError::MachineMismatch);
+        }
+        let mut stack = vec![0; depth];
+        for entry in stack.iter_mut() {
+            *entry = r.u16()?;
+        }
+        //Version 1 stored the index of the top entry, with -1 for an empty stack
+        let sp = if version == 1 { (r.u8()? as i8 as isize + 1) as usize } else { r.u16()? as usize };
+        if sp > depth {
+            return Err(StateError::Corrupt);
+        }
+        let delay = r.u8()?;
+        let sound = r.u8()?;
+        let mut keyboard = self.keyboard;
+        for key in keyboard.iter_mut() {
+            *key = r.bool()?;
+        }
+        let mut screen = Display::new();
+        screen.set_hires(r.bool()?);
+        screen.load_pixels(r.bytes(screen.pixels().len())?);
+        let mut rpl = self.rpl;
+        rpl.copy_from_slice(r.bytes(constants::NUM_RPL_FLAGS)?);
+        let planes = r.u8()?;
+        let mut audio_pattern = self.audio_pattern;
+        audio_pattern.copy_from_slice(r.bytes(constants::AUDIO_PATTERN_SIZE)?);
+        let pitch = r.u8()?;
+        let status = match r.u8()? {
+            0 => CpuStatus::Running,
+            1 => CpuStatus::AwaitingKeyPress { x: r.register()? },
+            2 => CpuStatus::AwaitingKeyRelease { x: r.register()?, key: r.register()? },
+            3 => CpuStatus::Halted,
+            4 => CpuStatus::Exited,
+            _ => return Err(StateError::Corrupt),
+        };
+        let rng_state = r.u64()?;
+
+        *self.bus.memory_mut() = mem;
+        self.cache = if self.config.decode_cache { Some(DecodeCache::new(self.bus.len())) } else { None };
+        self.regs = regs;
+        self.pc = pc;
+        self.idx = idx;
+        self.op_address = op_address;
+        self.stack = stack;
+        self.sp = sp;
+        self.delay = delay;
+        self.sound = sound;
+        self.keyboard = keyboard;
+        self.screen = screen;
+        self.rpl = rpl;
+        self.planes = planes;
+        self.audio_pattern = audio_pattern;
+        self.pitch = pitch;
+        if self.status == CpuStatus::Paused {
+            self.resume_status = status;
+        } else {
+            self.status = status;
+        }
+        self.rng.restore(rng_state);
+        self.redraw = true;
+        Ok(())
+    }
+}
+
+fn platform_id(platform: Platform) -> u8 {
+    match platform {
+        Platform::Chip8 => 0,
+        Platform::SuperChip => 1,
+        Platform::XoChip => 2,
+    }
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        let end = self.pos.checked_add(len).ok_or(StateError::Truncated)?;
+        let bytes = self.data.get(self.pos..end).ok_or(StateError::Truncated)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Corrupt),
+        }
+    }
+
+    fn register(&mut self) -> Result<u8, StateError> {
+        let value = self.u8()?;
+        if value < 16 { Ok(value) } else { Err(StateError::Corrupt) }
+    }
+
+    fn u16(&mut self) -> Result<u16, StateError> {
+        let mut buf = [0; 2];
+        buf.copy_from_slice(self.bytes(2)?);
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    fn u32(&mut self) -> Result<u32, StateError> {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn u64(&mut self) -> Result<u64, StateError> {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(buf))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::{Config, StateError};
+
+    /// Calls a subroutine that sets V1 and then spins, so a state has a non-empty stack.
+    const ROM: [u8; 8] = [0x22, 0x04, 0x12, 0x02, 0x61, 0x05, 0x12, 0x06];
+
+    fn running() -> Chip8 {
+        let mut chip8 = Chip8::new(&ROM, Config::default()).unwrap();
+        for _ in 0..4 {
+            chip8.tick([false; 16]).unwrap();
+        }
+        chip8
+    }
+
+    /// Where the stack depth is stored in a version 2 state.
+    fn depth_offset(chip8: &Chip8) -> usize {
+        MAGIC.len() + 2 + 1 + 4 + chip8.memory().len() + constants::NUM_REGISTERS + 6
+    }
+
+    /// Rewrites a version 2 state in the version 1 layout, with 8-bit stack fields and the stack
+    /// pointer stored as the index of the top entry.
+    fn downgrade(state: &[u8], chip8: &Chip8) -> Vec<u8> {
+        let depth_at = depth_offset(chip8);
+        let depth = u16::from_le_bytes([state[depth_at], state[depth_at + 1]]) as usize;
+        let sp_at = depth_at + 2 + depth * 2;
+        let sp = u16::from_le_bytes([state[sp_at], state[sp_at + 1]]);
+        let mut old = state[..depth_at].to_vec();
+        old[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&1u16.to_le_bytes());
+        old.push(depth as u8);
+        old.extend_from_slice(&state[depth_at + 2..sp_at]);
+        old.push((sp as i8 - 1) as u8);
+        old.extend_from_slice(&state[sp_at + 2..]);
+        old
+    }
+
+    #[test]
+    fn state_round_trips() {
+        let chip8 = running();
+        assert_eq!(chip8.registers()[1], 5);
+        let state = chip8.save_state();
+        let mut restored = Chip8::new(&ROM, Config::default()).unwrap();
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.save_state(), state);
+        assert_eq!((restored.pc(), restored.stack()), (chip8.pc(), chip8.stack()));
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let mut state = running().save_state();
+        state[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        let mut chip8 = Chip8::new(&ROM, Config::default()).unwrap();
+        assert_eq!(chip8.load_state(&state), Err(StateError::UnsupportedVersion { version: VERSION + 1, supported: VERSION }));
+    }
+
+    #[test]
+    fn rejects_data_without_the_magic() {
+        let mut chip8 = Chip8::new(&ROM, Config::default()).unwrap();
+        assert_eq!(chip8.load_state(b""), Err(StateError::NotASaveState));
+        assert_eq!(chip8.load_state(b"TJR"), Err(StateError::NotASaveState));
+        let mut state = running().save_state();
+        state[0] = b'X';
+        assert_eq!(chip8.load_state(&state), Err(StateError::NotASaveState));
+    }
+
+    #[test]
+    fn rejects_truncated_states_without_changing_anything() {
+        let state = running().save_state();
+        let mut chip8 = Chip8::new(&ROM, Config::default()).unwrap();
+        let before = chip8.save_state();
+        for len in MAGIC.len()..state.len() {
+            assert_eq!(chip8.load_state(&state[..len]), Err(StateError::Truncated), "cut at {}", len);
+        }
+        assert_eq!(chip8.save_state(), before);
+    }
+
+    #[test]
+    fn rejects_corrupt_fields() {
+        let chip8 = running();
+        let state = chip8.save_state();
+        let depth_at = depth_offset(&chip8);
+        let sp_at = depth_at + 2 + constants::STACK_DEPTH * 2;
+        let keys_at = sp_at + 4;
+        let status_at = state.len() - 9;
+        let mut target = Chip8::new(&ROM, Config::default()).unwrap();
+        for &(at, value) in [(sp_at, constants::STACK_DEPTH as u8 + 1), (keys_at, 2), (status_at, 9)].iter() {
+            let mut corrupt = state.clone();
+            corrupt[at] = value;
+            assert_eq!(target.load_state(&corrupt), Err(StateError::Corrupt), "byte {} set to {}", at, value);
+        }
+    }
+
+    #[test]
+    fn rejects_states_from_other_machines() {
+        let state = running().save_state();
+        let mut xo_chip = Chip8::new(&ROM, Config::new(Platform::XoChip)).unwrap();
+        assert_eq!(xo_chip.load_state(&state), Err(StateError::MachineMismatch));
+        let mut smaller = Chip8::new(&ROM, Config { memory_size: 2048, ..Config::default() }).unwrap();
+        assert_eq!(smaller.load_state(&state), Err(StateError::MachineMismatch));
+        let mut deeper = Chip8::new(&ROM, Config { stack_depth: 32, ..Config::default() }).unwrap();
+        assert_eq!(deeper.load_state(&state), Err(StateError::MachineMismatch));
+    }
+
+    #[test]
+    fn loads_version_1_states() {
+        let chip8 = running();
+        let state = chip8.save_state();
+        let old = downgrade(&state, &chip8);
+        assert_eq!(old.len(), state.len() - 2);
+        let mut restored = Chip8::new(&ROM, Config::default()).unwrap();
+        restored.load_state(&old).unwrap();
+        assert_eq!(restored.stack(), chip8.stack());
+        assert_eq!(restored.save_state(), state);
+
+        //An empty stack was stored as -1
+        let empty = Chip8::new(&ROM, Config::default()).unwrap();
+        let mut restored = running();
+        restored.load_state(&downgrade(&empty.save_state(), &empty)).unwrap();
+        assert!(restored.stack().is_empty());
+        assert_eq!(restored.save_state(), empty.save_state());
+    }
+}
@@ -0,0 +1,68 @@
+use std::fmt;
+
+use super::Instruction;
+
+/// 1802 machine cycles per 60 Hz frame: a 1.7609 MHz clock with eight clocks per machine cycle.
+pub const VIP_CYCLES_PER_FRAME: i64 = 3668;
+/// Cycles each frame spends in the display DMA (128 lines of 8 bytes) and the interrupt routine
+/// that counts the timers down, and that are therefore unavailable to the interpreter.
+pub const VIP_INTERRUPT_CYCLES: i64 = 1024 + 46;
+/// Cycles the interpreter's fetch and dispatch loop spends before any instruction executes.
+const VIP_DISPATCH_CYCLES: u32 = 40;
+
+/// How instructions are paced against the 60 Hz frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimingMode {
+    /// A fixed number of instructions per frame derived from the configured clock speed.
+    Fast,
+    /// Each instruction costs what it did in the original COSMAC VIP interpreter, and DXYN
+    /// waits for the vertical blank interrupt before the program continues.
+    CosmacVip,
+}
+
+impl TimingMode {
+    pub const ALL: [TimingMode; 2] = [TimingMode::Fast, TimingMode::CosmacVip];
+}
+
+impl fmt::Display for TimingMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TimingMode::Fast => "Fast",
+            TimingMode::CosmacVip => "COSMAC VIP",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Approximate machine cycles the VIP interpreter needs for `instruction`, including dispatch.
+/// Costs that depend on operands (sprite height, register count) are modelled; costs that depend
+/// on runtime values, such as whether a skip is taken or a sprite is byte-aligned, use the
+/// slower case.
+pub fn vip_cycles(instruction: &Instruction) -> u32 {
+    let execute = match *instruction {
+        Instruction::Cls => 24 + 3078,
+        Instruction::Ret => 10,
+        Instruction::Jp(_) => 12,
+        Instruction::Call(_) => 26,
+        Instruction::SeByte { .. } | Instruction::SneByte { .. } => 14,
+        Instruction::SeReg { .. } | Instruction::SneReg { .. } => 18,
+        Instruction::LdByte { .. } => 6,
+        Instruction::AddByte { .. } => 10,
+        Instruction::LdReg { .. } | Instruction::Or { .. } | Instruction::And { .. }
+        | Instruction::Xor { .. } | Instruction::AddReg { .. } | Instruction::Sub { .. }
+        | Instruction::Shr { .. } | Instruction::Subn { .. } | Instruction::Shl { .. } => 44,
+        Instruction::LdI(_) => 12,
+        Instruction::JpV0(_) => 22,
+        Instruction::Rnd { .. } => 36,
+        Instruction::Drw { n, .. } => 26 + 66 * n as u32,
+        Instruction::Skp(_) | Instruction::Sknp(_) => 18,
+        Instruction::LdVxDt(_) | Instruction::LdDtVx(_) | Instruction::LdStVx(_) => 10,
+        Instruction::LdVxK(_) => 18,
+        Instruction::AddI(_) | Instruction::LdF(_) => 16,
+        Instruction::Bcd(_) => 152,
+        Instruction::Store(x) | Instruction::Load(x) => 14 + 14 * (x as u32 + 1),
+        //Not part of the VIP instruction set; charge a typical register operation
+        _ => 44,
+    };
+    VIP_DISPATCH_CYCLES + execute
+}
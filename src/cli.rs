@@ -0,0 +1,136 @@
+//! Subcommands that run without a window, for scripts and test pipelines on headless machines.
+
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use tjr_chip8::assembler::{self, AssemblyError};
+use tjr_chip8::chip8::{Platform, TimingMode};
+
+mod assemble;
+mod disasm;
+mod run;
+
+/// The interpreter stopped with a `Chip8Error`.
+const EXIT_CPU_ERROR: i32 = 1;
+/// `assemble` found an error in the source.
+const EXIT_ASSEMBLY_ERROR: i32 = 1;
+/// Bad arguments or an unreadable or unwritable file.
+const EXIT_USAGE: i32 = 2;
+
+const USAGE: &str = "\
+usage: tjr-chip8 [COMMAND]
+
+Without a command the emulator window opens.
+
+commands:
+  run ROM [OPTIONS]    run a ROM headlessly and dump the screen and registers
+  disasm ROM [OPTIONS] print a ROM as an Octo listing
+  assemble SOURCE [--output FILE]
+                       assemble an Octo source into a ROM (default SOURCE with .ch8)
+
+ROM arguments ending in .8o are assembled before use.
+
+run options:
+  --frames N           stop after N frames of 1/60 s (default 60 unless --instructions is given)
+  --instructions N     stop after N instructions
+  --platform NAME      chip8, superchip or xochip (default chip8)
+  --clock HZ           instructions per second (default 800)
+  --timing MODE        fast or vip (default fast)
+  --seed N             seed for CXNN (default fixed)
+  --keys SCRIPT        keys held from a frame on, e.g. 30:5,32:,90:4A
+  --ascii FILE         write the screen as text, - for stdout
+  --png FILE           write the screen as a PNG image
+  --scale N            pixels per CHIP-8 pixel in the PNG (default 1)
+  --json FILE          write the registers as JSON, - for stdout
+
+Without any output option the screen and registers are written to stdout.
+
+disasm options:
+  --platform NAME      instruction set to decode, as for run (default chip8)
+  --load-address ADDR  where the ROM is loaded and execution starts (default 0x200)
+  --output FILE        write the listing to FILE instead of stdout";
+
+/// Runs the subcommand in `args` and returns the process exit code.
+pub fn main(args: &[String]) -> i32 {
+    let result = match args.first().map(String::as_str) {
+        Some("run") => run::main(&args[1..]),
+        Some("disasm") => disasm::main(&args[1..]),
+        Some("assemble") => assemble::main(&args[1..]),
+        Some("help") | Some("--help") | Some("-h") => {
+            println!("{}", USAGE);
+            return 0;
+        },
+        Some(command) => Err(format!("unknown command '{}'\n\n{}", command, USAGE)),
+        None => Err(format!("no command given\n\n{}", USAGE)),
+    };
+    match result {
+        Ok(code) => code,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            EXIT_USAGE
+        },
+    }
+}
+
+/// The argument following `flag`.
+fn value<'a>(args: &mut impl Iterator<Item = &'a String>, flag: &str) -> Result<&'a str, String> {
+    args.next().map(String::as_str).ok_or_else(|| format!("{} needs a value", flag))
+}
+
+/// Parses a decimal or `0x`-prefixed hexadecimal number.
+fn number<T: FromStr + TryFrom<u64>>(text: &str, flag: &str) -> Result<T, String> {
+    let parsed = match text.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    };
+    parsed.and_then(|n| T::try_from(n).ok())
+        .ok_or_else(|| format!("{} expects a number, got '{}'", flag, text))
+}
+
+/// Writes `data` to the file at `path`, or to stdout if `path` is `-`.
+fn write_output(path: &str, data: &[u8]) -> Result<(), String> {
+    let result = if path == "-" {
+        io::stdout().write_all(data)
+    } else {
+        fs::write(path, data)
+    };
+    result.map_err(|e| format!("unable to write {}: {}", path, e))
+}
+
+/// Reads the ROM at `path`, assembling it first if it is an Octo source.
+fn read_rom(path: &str) -> Result<Vec<u8>, String> {
+    if !is_source(path) {
+        return fs::read(path).map_err(|e| format!("unable to read {}: {}", path, e));
+    }
+    let source = fs::read_to_string(path).map_err(|e| format!("unable to read {}: {}", path, e))?;
+    assembler::assemble(&source).map_err(|e| source_error(path, &e))
+}
+
+fn is_source(path: &str) -> bool {
+    matches!(Path::new(path).extension(), Some(extension) if extension.eq_ignore_ascii_case("8o"))
+}
+
+/// `error` in the `file:line:column: message` form editors understand.
+fn source_error(path: &str, error: &AssemblyError) -> String {
+    format!("{}:{}:{}: {}", path, error.line, error.column, error.message)
+}
+
+fn platform(text: &str) -> Result<Platform, String> {
+    match text.to_ascii_lowercase().as_str() {
+        "chip8" | "chip-8" => Ok(Platform::Chip8),
+        "superchip" | "schip" | "super-chip" => Ok(Platform::SuperChip),
+        "xochip" | "xo-chip" => Ok(Platform::XoChip),
+        _ => Err(format!("unknown platform '{}'", text)),
+    }
+}
+
+fn timing(text: &str) -> Result<TimingMode, String> {
+    match text.to_ascii_lowercase().as_str() {
+        "fast" => Ok(TimingMode::Fast),
+        "vip" | "cosmac-vip" => Ok(TimingMode::CosmacVip),
+        _ => Err(format!("unknown timing mode '{}'", text)),
+    }
+}
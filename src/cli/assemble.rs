@@ -0,0 +1,34 @@
+//! `assemble`: turns an Octo source into a ROM file.
+
+use std::fs;
+use std::path::Path;
+
+use tjr_chip8::assembler::assemble;
+
+use super::{source_error, value, write_output, EXIT_ASSEMBLY_ERROR};
+
+pub fn main(args: &[String]) -> Result<i32, String> {
+    let mut source_path = None;
+    let mut output = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--output" => output = Some(value(&mut args, arg)?.to_string()),
+            flag if flag.starts_with("--") => return Err(format!("unknown option '{}'", flag)),
+            path if source_path.is_none() => source_path = Some(path.to_string()),
+            extra => return Err(format!("unexpected argument '{}'", extra)),
+        }
+    }
+    let source_path = source_path.ok_or("assemble needs a source file")?;
+    let source = fs::read_to_string(&source_path).map_err(|e| format!("unable to read {}: {}", source_path, e))?;
+    let rom = match assemble(&source) {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("{}", source_error(&source_path, &e));
+            return Ok(EXIT_ASSEMBLY_ERROR);
+        },
+    };
+    let output = output.unwrap_or_else(|| Path::new(&source_path).with_extension("ch8").to_string_lossy().into_owned());
+    write_output(&output, &rom)?;
+    Ok(0)
+}
@@ -0,0 +1,32 @@
+//! `disasm`: prints a ROM as an Octo listing.
+
+use tjr_chip8::chip8::{Config, Platform};
+use tjr_chip8::disassembler::disassemble;
+
+use super::{number, platform, read_rom, value, write_output};
+
+pub fn main(args: &[String]) -> Result<i32, String> {
+    let mut rom = None;
+    let mut platform_choice = Platform::Chip8;
+    let mut load_address = None;
+    let mut output = String::from("-");
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--platform" => platform_choice = platform(value(&mut args, arg)?)?,
+            "--load-address" => load_address = Some(number(value(&mut args, arg)?, arg)?),
+            "--output" => output = value(&mut args, arg)?.to_string(),
+            flag if flag.starts_with("--") => return Err(format!("unknown option '{}'", flag)),
+            path if rom.is_none() => rom = Some(path.to_string()),
+            extra => return Err(format!("unexpected argument '{}'", extra)),
+        }
+    }
+    let rom = rom.ok_or("disasm needs a ROM file")?;
+    let data = read_rom(&rom)?;
+    let mut config = Config::new(platform_choice);
+    if let Some(address) = load_address {
+        config.load_address = address;
+    }
+    write_output(&output, disassemble(&data, &config).as_bytes())?;
+    Ok(0)
+}
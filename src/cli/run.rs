@@ -0,0 +1,241 @@
+//! `run`: executes a ROM for a fixed number of frames or instructions with scripted input and
+//! dumps the final screen and registers.
+
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::BufWriter;
+
+use serde_json::json;
+use tjr_chip8::chip8::{Chip8, Chip8Error, Config, CpuStatus, FrameScheduler, Platform, TimingMode};
+
+use super::{number, platform, read_rom, timing, value, write_output, EXIT_CPU_ERROR};
+
+const DEFAULT_CLOCK_SPEED: u32 = 800;
+const DEFAULT_FRAMES: u64 = 60;
+/// Matches the emulator window's default colours.
+const PALETTE: [[u8; 3]; 4] = [[0, 0, 0], [255, 255, 255], [170, 170, 170], [85, 85, 85]];
+
+struct Options {
+    rom: String,
+    config: Config,
+    clock_speed: u32,
+    timing: TimingMode,
+    frames: Option<u64>,
+    instructions: Option<u64>,
+    keys: KeyScript,
+    ascii: Option<String>,
+    png: Option<String>,
+    scale: u32,
+    json: Option<String>,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut rom = None;
+        let mut platform_choice = Platform::Chip8;
+        let mut seed = None;
+        let mut options = Options {
+            rom: String::new(),
+            config: Config::default(),
+            clock_speed: DEFAULT_CLOCK_SPEED,
+            timing: TimingMode::Fast,
+            frames: None,
+            instructions: None,
+            keys: KeyScript::default(),
+            ascii: None,
+            png: None,
+            scale: 1,
+            json: None,
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--frames" => options.frames = Some(number(value(&mut args, arg)?, arg)?),
+                "--instructions" => options.instructions = Some(number(value(&mut args, arg)?, arg)?),
+                "--platform" => platform_choice = platform(value(&mut args, arg)?)?,
+                "--clock" => options.clock_speed = number(value(&mut args, arg)?, arg)?,
+                "--timing" => options.timing = timing(value(&mut args, arg)?)?,
+                "--seed" => seed = Some(number(value(&mut args, arg)?, arg)?),
+                "--keys" => options.keys = KeyScript::parse(value(&mut args, arg)?)?,
+                "--ascii" => options.ascii = Some(value(&mut args, arg)?.to_string()),
+                "--png" => options.png = Some(value(&mut args, arg)?.to_string()),
+                "--scale" => options.scale = number(value(&mut args, arg)?, arg)?,
+                "--json" => options.json = Some(value(&mut args, arg)?.to_string()),
+                flag if flag.starts_with("--") => return Err(format!("unknown option '{}'", flag)),
+                path if rom.is_none() => rom = Some(path.to_string()),
+                extra => return Err(format!("unexpected argument '{}'", extra)),
+            }
+        }
+        options.rom = rom.ok_or("run needs a ROM file")?;
+        options.config = Config::new(platform_choice);
+        if let Some(seed) = seed {
+            options.config.seed = seed;
+        }
+        if options.frames.is_none() && options.instructions.is_none() {
+            options.frames = Some(DEFAULT_FRAMES);
+        }
+        if options.clock_speed == 0 {
+            return Err(String::from("--clock must be at least 1"));
+        }
+        if options.scale == 0 {
+            return Err(String::from("--scale must be at least 1"));
+        }
+        if options.ascii.is_none() && options.png.is_none() && options.json.is_none() {
+            options.ascii = Some(String::from("-"));
+            options.json = Some(String::from("-"));
+        }
+        Ok(options)
+    }
+}
+
+/// The keypad over time: each entry holds its keys from its frame until the next entry.
+#[derive(Default)]
+struct KeyScript {
+    events: Vec<(u64, [bool; 16])>,
+}
+
+impl KeyScript {
+    /// Parses `FRAME:KEYS` entries separated by commas, where `KEYS` are hex digits and may be
+    /// empty to release everything, e.g. `30:5,32:,90:4A`.
+    fn parse(script: &str) -> Result<Self, String> {
+        let mut events = Vec::new();
+        for entry in script.split(',').filter(|entry| !entry.is_empty()) {
+            let (frame, keys) = entry.split_once(':')
+                .ok_or_else(|| format!("key script entry '{}' is not FRAME:KEYS", entry))?;
+            let frame: u64 = number(frame, "--keys")?;
+            let mut held = [false; 16];
+            for digit in keys.chars() {
+                let key = digit.to_digit(16).ok_or_else(|| format!("'{}' is not a hex key", digit))?;
+                held[key as usize] = true;
+            }
+            events.push((frame, held));
+        }
+        events.sort_by_key(|&(frame, _)| frame);
+        Ok(Self { events })
+    }
+
+    fn keys_at(&self, frame: u64) -> [bool; 16] {
+        self.events.iter()
+            .take_while(|&&(start, _)| start <= frame)
+            .last()
+            .map_or([false; 16], |&(_, keys)| keys)
+    }
+
+    /// Whether the keypad in `frame` and every later frame is the same as in the frame before.
+    fn is_finished(&self, frame: u64) -> bool {
+        !matches!(self.events.last(), Some(&(start, _)) if start >= frame)
+    }
+}
+
+#[derive(Default)]
+struct Summary {
+    frames: u64,
+    instructions: u64,
+    error: Option<Chip8Error>,
+}
+
+pub fn main(args: &[String]) -> Result<i32, String> {
+    let options = Options::parse(args)?;
+    let rom = read_rom(&options.rom)?;
+    let mut chip8 = Chip8::new(&rom, options.config).map_err(|e| e.to_string())?;
+    let mut scheduler = FrameScheduler::new(options.clock_speed);
+    scheduler.set_timing(options.timing);
+
+    let summary = run(&mut chip8, &mut scheduler, &options);
+    if let Some(error) = summary.error {
+        eprintln!("error: {}", error);
+    }
+
+    if let Some(path) = &options.ascii {
+        write_output(path, chip8.screen().to_ascii().as_bytes())?;
+    }
+    if let Some(path) = &options.png {
+        write_png(path, &chip8, options.scale).map_err(|e| format!("unable to write {}: {}", path, e))?;
+    }
+    if let Some(path) = &options.json {
+        let mut text = serde_json::to_string_pretty(&registers(&chip8, &summary)).map_err(|e| e.to_string())?;
+        text.push('\n');
+        write_output(path, text.as_bytes())?;
+    }
+    Ok(if summary.error.is_some() { EXIT_CPU_ERROR } else { 0 })
+}
+
+fn run(chip8: &mut Chip8, scheduler: &mut FrameScheduler, options: &Options) -> Summary {
+    let mut summary = Summary::default();
+    loop {
+        let frames_done = matches!(options.frames, Some(frames) if summary.frames >= frames);
+        let instructions_done = matches!(options.instructions, Some(count) if summary.instructions >= count);
+        //With only an instruction limit, a wait for a key that will never come would never end
+        let stuck = options.frames.is_none() && chip8.status.is_waiting_for_key()
+            && options.keys.is_finished(summary.frames);
+        if frames_done || instructions_done || stuck || !is_live(chip8.status) {
+            return summary;
+        }
+        let keys = options.keys.keys_at(summary.frames);
+        let result = match options.instructions {
+            Some(count) => {
+                let limit = u32::try_from(count - summary.instructions).unwrap_or(u32::MAX);
+                scheduler.run_frame_limited(chip8, keys, limit)
+            },
+            None => scheduler.run_frame(chip8, keys).map(|executed| (executed, true)),
+        };
+        match result {
+            Ok((executed, complete)) => {
+                summary.instructions += executed as u64;
+                if complete {
+                    summary.frames += 1;
+                }
+            },
+            Err(error) => {
+                summary.error = Some(error);
+                return summary;
+            },
+        }
+    }
+}
+
+/// Whether running more frames can still change anything.
+fn is_live(status: CpuStatus) -> bool {
+    status == CpuStatus::Running || status.is_waiting_for_key()
+}
+
+fn registers(chip8: &Chip8, summary: &Summary) -> serde_json::Value {
+    let status = match chip8.status {
+        CpuStatus::Running => "running",
+        CpuStatus::AwaitingKeyPress { .. } | CpuStatus::AwaitingKeyRelease { .. } => "awaiting_key",
+        CpuStatus::Paused => "paused",
+        CpuStatus::Halted => "halted",
+        CpuStatus::Exited => "exited",
+    };
+    json!({
+        "status": status,
+        "error": summary.error.map(|error| error.to_string()),
+        "frames": summary.frames,
+        "instructions": summary.instructions,
+        "pc": chip8.pc(),
+        "i": chip8.index(),
+        "v": chip8.registers(),
+        "stack": chip8.stack(),
+        "delay_timer": chip8.delay_timer(),
+        "sound_timer": chip8.sound_timer(),
+        "hires": chip8.screen().is_hires(),
+    })
+}
+
+fn write_png(path: &str, chip8: &Chip8, scale: u32) -> Result<(), Box<dyn std::error::Error>> {
+    let screen = chip8.screen();
+    let scale = scale as usize;
+    let (width, height) = (screen.width() * scale, screen.height() * scale);
+    let mut data = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = screen.pixel(x / scale, y / scale) & 0x3;
+            data.extend_from_slice(&PALETTE[pixel as usize]);
+        }
+    }
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&data)?;
+    Ok(())
+}
@@ -0,0 +1,362 @@
+//! Offline lookup of ROM metadata and settings in the format of the community CHIP-8 database
+//! (`programs.json`, `sha1-hashes.json` and `platforms.json`). ROMs are identified by the SHA-1
+//! of their image.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::chip8::{Config, Platform, Quirks};
+
+mod sha1;
+
+pub use sha1::hex_digest as sha1_hex;
+
+const BUNDLED_PROGRAMS: &str = include_str!("../data/chip8-database/programs.json");
+const BUNDLED_HASHES: &str = include_str!("../data/chip8-database/sha1-hashes.json");
+const BUNDLED_PLATFORMS: &str = include_str!("../data/chip8-database/platforms.json");
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Program {
+    title: String,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    release: Option<String>,
+    #[serde(default)]
+    authors: Vec<String>,
+    #[serde(default)]
+    roms: HashMap<String, Rom>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Rom {
+    #[serde(default)]
+    platforms: Vec<String>,
+    #[serde(default)]
+    quirky_platforms: HashMap<String, DatabaseQuirks>,
+    #[serde(default)]
+    tickrate: Option<u32>,
+    #[serde(default)]
+    start_address: Option<u16>,
+    #[serde(default)]
+    keys: HashMap<String, u8>,
+    #[serde(default)]
+    colors: Option<Colors>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Colors {
+    #[serde(default)]
+    pixels: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct DatabaseQuirks {
+    shift: Option<bool>,
+    memory_increment_by_x: Option<bool>,
+    memory_leave_i_unchanged: Option<bool>,
+    wrap: Option<bool>,
+    jump: Option<bool>,
+    logic: Option<bool>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlatformEntry {
+    id: String,
+    #[serde(default)]
+    default_tickrate: Option<u32>,
+    #[serde(default)]
+    quirks: DatabaseQuirks,
+}
+
+/// The keypad keys a ROM uses for directions and actions, for hosts that map them onto more
+/// natural controls such as the arrow keys.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyMapping {
+    pub up: Option<u8>,
+    pub down: Option<u8>,
+    pub left: Option<u8>,
+    pub right: Option<u8>,
+    pub a: Option<u8>,
+    pub b: Option<u8>,
+}
+
+/// What the database knows about one ROM image. Settings the database leaves open are `None`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomInfo {
+    pub sha1: String,
+    pub title: String,
+    pub authors: Vec<String>,
+    pub release: Option<String>,
+    pub description: Option<String>,
+    /// The first of the ROM's platforms this emulator supports.
+    pub platform: Option<Platform>,
+    pub quirks: Option<Quirks>,
+    /// Instructions per 60 Hz frame.
+    pub tickrate: Option<u32>,
+    pub start_address: Option<u16>,
+    pub keys: KeyMapping,
+    /// Background, plane 1, plane 2 and both planes, as far as the database specifies them.
+    pub palette: Vec<[u8; 3]>,
+}
+
+impl RomInfo {
+    /// A `Config` for the ROM's platform with the database's quirks and start address applied.
+    pub fn config(&self) -> Option<Config> {
+        let mut config = Config::new(self.platform?);
+        if let Some(quirks) = self.quirks {
+            config.quirks = quirks;
+        }
+        if let Some(address) = self.start_address {
+            config.load_address = address;
+        }
+        Some(config)
+    }
+}
+
+pub struct RomDatabase {
+    programs: Vec<Program>,
+    hashes: HashMap<String, usize>,
+    platforms: Vec<PlatformEntry>,
+}
+
+impl RomDatabase {
+    /// The database compiled into the crate from `data/chip8-database`.
+    pub fn bundled() -> Self {
+        Self::from_json(BUNDLED_PROGRAMS, BUNDLED_HASHES, BUNDLED_PLATFORMS)
+            .expect("the bundled ROM database is valid JSON")
+    }
+
+    /// Reads `programs.json`, `sha1-hashes.json` and `platforms.json` from `directory`, e.g. a
+    /// checkout of the community database that is newer than the bundled copy.
+    pub fn load(directory: &Path) -> io::Result<Self> {
+        let read = |name: &str| fs::read_to_string(directory.join(name));
+        Self::from_json(&read("programs.json")?, &read("sha1-hashes.json")?, &read("platforms.json")?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn from_json(programs: &str, hashes: &str, platforms: &str) -> Result<Self, serde_json::Error> {
+        Ok(Self {
+            programs: serde_json::from_str(programs)?,
+            hashes: serde_json::from_str(hashes)?,
+            platforms: serde_json::from_str(platforms)?,
+        })
+    }
+
+    pub fn lookup(&self, rom: &[u8]) -> Option<RomInfo> {
+        let sha1 = sha1_hex(rom);
+        let program = self.programs.get(*self.hashes.get(&sha1)?)?;
+        let entry = program.roms.get(&sha1)?;
+        let platform_id = entry.platforms.iter().find(|id| platform(id).is_some());
+        let quirks = platform_id.map(|id| {
+            let defaults = self.platforms.iter()
+                .find(|p| &p.id == id)
+                .map(|p| p.quirks)
+                .unwrap_or_default();
+            let overrides = entry.quirky_platforms.get(id).copied().unwrap_or_default();
+            quirks(platform(id).unwrap(), defaults, overrides)
+        });
+        let tickrate = entry.tickrate.or_else(|| {
+            let id = platform_id?;
+            self.platforms.iter().find(|p| &p.id == id)?.default_tickrate
+        });
+        let key = |name: &str| entry.keys.get(name).copied().filter(|key| *key < 16);
+        Some(RomInfo {
+            title: program.title.clone(),
+            authors: program.authors.clone(),
+            release: program.release.clone(),
+            description: program.description.clone(),
+            platform: platform_id.and_then(|id| platform(id)),
+            quirks,
+            tickrate,
+            start_address: entry.start_address,
+            keys: KeyMapping {
+                up: key("up"),
+                down: key("down"),
+                left: key("left"),
+                right: key("right"),
+                a: key("a"),
+                b: key("b"),
+            },
+            palette: entry.colors.as_ref()
+                .map(|colors| colors.pixels.iter().filter_map(|c| parse_color(c)).collect())
+                .unwrap_or_default(),
+            sha1,
+        })
+    }
+}
+
+/// Maps a database platform id onto the instruction set that runs it, if this emulator has one.
+fn platform(id: &str) -> Option<Platform> {
+    match id {
+        "originalChip8" | "hybridVIP" | "modernChip8" | "chip48" => Some(Platform::Chip8),
+        "superchip1" | "superchip" => Some(Platform::SuperChip),
+        "xochip" => Some(Platform::XoChip),
+        _ => None,
+    }
+}
+
+/// Starts from the emulator's own quirks for `platform` and applies whatever the database
+/// specifies, with ROM-specific overrides taking precedence over the platform's defaults.
+fn quirks(platform: Platform, defaults: DatabaseQuirks, overrides: DatabaseQuirks) -> Quirks {
+    let pick = |f: fn(&DatabaseQuirks) -> Option<bool>| f(&overrides).or_else(|| f(&defaults));
+    let mut quirks = platform.default_quirks();
+    //The database describes shifting VX in place; this emulator describes reading VY
+    if let Some(shift) = pick(|q| q.shift) {
+        quirks.shift_reads_vy = !shift;
+    }
+    //I += X is not modelled separately; it is closest to incrementing I
+    if let Some(true) = pick(|q| q.memory_increment_by_x) {
+        quirks.load_store_increments_i = true;
+    }
+    if let Some(leave) = pick(|q| q.memory_leave_i_unchanged) {
+        quirks.load_store_increments_i = !leave;
+    }
+    if let Some(wrap) = pick(|q| q.wrap) {
+        quirks.wrap_sprites = wrap;
+    }
+    if let Some(jump) = pick(|q| q.jump) {
+        quirks.jump_uses_vx = jump;
+    }
+    if let Some(logic) = pick(|q| q.logic) {
+        quirks.logic_resets_vf = logic;
+    }
+    quirks
+}
+
+/// Parses `#rrggbb`, `#rgb` or one of the few colour names the database uses.
+fn parse_color(color: &str) -> Option<[u8; 3]> {
+    let hex = match color.strip_prefix('#') {
+        Some(hex) => hex,
+        None => return match color {
+            "black" => Some([0, 0, 0]),
+            "white" => Some([255, 255, 255]),
+            _ => None,
+        },
+    };
+    let channel = |s: &str| u8::from_str_radix(s, 16).ok();
+    match hex.len() {
+        6 => Some([channel(&hex[0..2])?, channel(&hex[2..4])?, channel(&hex[4..6])?]),
+        3 => Some([channel(&hex[0..1])? * 17, channel(&hex[1..2])? * 17, channel(&hex[2..3])? * 17]),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROM: &[u8] = b"\x00\xE0\x12\x00";
+    const OTHER_ROM: &[u8] = b"\x12\x00";
+
+    const PLATFORMS: &str = r##"[
+        { "id": "originalChip8", "defaultTickrate": 15,
+          "quirks": { "shift": false, "memoryLeaveIUnchanged": false, "wrap": false, "jump": false, "logic": true } },
+        { "id": "superchip", "defaultTickrate": 30,
+          "quirks": { "shift": true, "memoryLeaveIUnchanged": true, "wrap": false, "jump": true, "logic": false } },
+        { "id": "megachip8", "defaultTickrate": 1000, "quirks": {} }
+    ]"##;
+
+    /// One program for `ROM` on SUPER-CHIP with ROM-specific overrides, and one for `OTHER_ROM`
+    /// on a platform this emulator does not have.
+    fn database() -> RomDatabase {
+        let programs = format!(r##"[
+            {{ "title": "Test Game", "description": "A test", "release": "1991", "authors": ["Someone"],
+               "roms": {{ "{}": {{
+                   "platforms": ["superchip", "originalChip8"],
+                   "quirkyPlatforms": {{ "superchip": {{ "wrap": true, "jump": false }} }},
+                   "startAddress": 768,
+                   "keys": {{ "up": 5, "down": 8, "left": 7, "right": 9, "a": 6, "b": 16 }},
+                   "colors": {{ "pixels": ["#102030", "#fff", "white", "bogus"] }}
+               }} }} }},
+            {{ "title": "Mega Game",
+               "roms": {{ "{}": {{ "platforms": ["megachip8"], "tickrate": 200 }} }} }}
+        ]"##, sha1_hex(ROM), sha1_hex(OTHER_ROM));
+        let hashes = format!(r#"{{ "{}": 0, "{}": 1 }}"#, sha1_hex(ROM), sha1_hex(OTHER_ROM));
+        RomDatabase::from_json(&programs, &hashes, PLATFORMS).unwrap()
+    }
+
+    #[test]
+    fn lookup_finds_metadata() {
+        let info = database().lookup(ROM).unwrap();
+        assert_eq!(info.sha1, sha1_hex(ROM));
+        assert_eq!(info.title, "Test Game");
+        assert_eq!(info.authors, ["Someone"]);
+        assert_eq!(info.release.as_deref(), Some("1991"));
+        assert_eq!(info.description.as_deref(), Some("A test"));
+        assert_eq!(info.platform, Some(Platform::SuperChip));
+        assert_eq!(info.start_address, Some(0x300));
+        //The platform's default applies when the ROM gives no tickrate
+        assert_eq!(info.tickrate, Some(30));
+    }
+
+    #[test]
+    fn unknown_roms_are_not_found() {
+        assert_eq!(database().lookup(b"not in the database"), None);
+    }
+
+    #[test]
+    fn quirks_combine_platform_defaults_and_overrides() {
+        let quirks = database().lookup(ROM).unwrap().quirks.unwrap();
+        //From the platform: the database's shift means shifting VX in place
+        assert!(!quirks.shift_reads_vy);
+        assert!(!quirks.load_store_increments_i);
+        assert!(!quirks.logic_resets_vf);
+        //From the ROM, overriding the platform
+        assert!(quirks.wrap_sprites);
+        assert!(!quirks.jump_uses_vx);
+    }
+
+    #[test]
+    fn config_applies_quirks_and_start_address() {
+        let info = database().lookup(ROM).unwrap();
+        let config = info.config().unwrap();
+        assert_eq!(config.platform, Platform::SuperChip);
+        assert_eq!(config.quirks, info.quirks.unwrap());
+        assert_eq!(config.load_address, 0x300);
+    }
+
+    #[test]
+    fn unsupported_platforms_leave_settings_open() {
+        let info = database().lookup(OTHER_ROM).unwrap();
+        assert_eq!(info.title, "Mega Game");
+        assert_eq!(info.platform, None);
+        assert_eq!(info.quirks, None);
+        assert_eq!(info.tickrate, Some(200));
+        assert_eq!(info.config(), None);
+    }
+
+    #[test]
+    fn keys_outside_the_keypad_are_dropped() {
+        let keys = database().lookup(ROM).unwrap().keys;
+        assert_eq!(keys, KeyMapping { up: Some(5), down: Some(8), left: Some(7), right: Some(9), a: Some(6), b: None });
+    }
+
+    #[test]
+    fn palette_accepts_hex_and_names() {
+        let palette = database().lookup(ROM).unwrap().palette;
+        assert_eq!(palette, [[0x10, 0x20, 0x30], [255, 255, 255], [255, 255, 255]]);
+        assert_eq!(parse_color("#000"), Some([0, 0, 0]));
+        assert_eq!(parse_color("black"), Some([0, 0, 0]));
+        assert_eq!(parse_color("#12345"), None);
+        assert_eq!(parse_color("#gg0000"), None);
+    }
+
+    #[test]
+    fn bundled_database_knows_the_test_roms() {
+        let database = RomDatabase::bundled();
+        let planes = database.lookup(include_bytes!("../tests/golden/roms/planes.ch8")).unwrap();
+        assert_eq!(planes.platform, Some(Platform::XoChip));
+        assert_eq!(planes.palette.len(), 4);
+        let hires = database.lookup(include_bytes!("../tests/golden/roms/hires.ch8")).unwrap();
+        assert_eq!(hires.platform, Some(Platform::SuperChip));
+    }
+}
@@ -0,0 +1,341 @@
+//! Turns a ROM back into an Octo listing. Control flow is followed from the load address
+//! through jumps, calls and both outcomes of every skip, so only bytes the interpreter can
+//! actually reach as code are decoded; everything else is rendered as data. Instructions are
+//! decoded exactly as `Chip8` fetches them, including the platform check, and the listing
+//! assembles back to the same bytes.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use crate::chip8::constants::{MAX_MEMORY_SIZE, PC_INITIAL};
+use crate::chip8::{Config, Instruction};
+
+/// Data bytes per line of a byte table.
+const BYTES_PER_LINE: usize = 8;
+
+/// Why an address gets a label. A target reached in several ways is named after the strongest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Target {
+    /// `i` is pointed at it.
+    Data,
+    /// `jump` or `jump0` goes there.
+    Jump,
+    /// `:call` goes there.
+    Call,
+}
+
+/// One line of the listing, in address order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Item {
+    Code(Instruction),
+    Data(u8),
+}
+
+/// The result of analysing a ROM: which addresses hold reachable instructions, which are data,
+/// and the labels invented for jump, call and `i` targets. `Display` renders the listing.
+#[derive(Debug, Clone)]
+pub struct Disassembly {
+    load_address: u16,
+    items: BTreeMap<u16, Item>,
+    labels: BTreeMap<u16, String>,
+}
+
+impl Disassembly {
+    /// Analyses `rom` as `config` would load and run it. Only the platform and load address of
+    /// `config` are used.
+    pub fn new(rom: &[u8], config: &Config) -> Self {
+        let start = config.load_address as usize;
+        let end = (start + rom.len()).min(MAX_MEMORY_SIZE);
+        let mut memory = vec![0; end];
+        memory[start..].copy_from_slice(&rom[..end - start]);
+        let decode = |address: u16| -> Option<Instruction> {
+            let instruction = Instruction::read(&memory, address as usize).ok()?;
+            let inside = address as usize >= start && address as usize + instruction.size() as usize <= end;
+            if inside && instruction.platform() <= config.platform { Some(instruction) } else { None }
+        };
+
+        let mut code = BTreeMap::new();
+        let mut targets = BTreeMap::new();
+        let mut mark = |address: u16, target: Target| {
+            let strongest = targets.entry(address).or_insert(target);
+            *strongest = target.max(*strongest);
+        };
+        let mut pending = vec![config.load_address];
+        while let Some(address) = pending.pop() {
+            if code.contains_key(&address) {
+                continue;
+            }
+            let instruction = match decode(address) {
+                Some(instruction) => instruction,
+                None => continue,
+            };
+            code.insert(address, instruction);
+            let next = address.wrapping_add(instruction.size());
+            match instruction {
+                Instruction::Jp(target) => {
+                    mark(target, Target::Jump);
+                    pending.push(target);
+                },
+                //The offset is only known at run time; the base is where a zero offset lands
+                Instruction::JpV0(target) => {
+                    mark(target, Target::Jump);
+                    pending.push(target);
+                },
+                Instruction::Call(target) => {
+                    mark(target, Target::Call);
+                    pending.push(target);
+                    pending.push(next);
+                },
+                Instruction::Ret | Instruction::Exit => (),
+                Instruction::LdI(target) | Instruction::LdILong(target) => {
+                    mark(target, Target::Data);
+                    pending.push(next);
+                },
+                skip if skip.is_skip() => {
+                    pending.push(next);
+                    //A skip steps over a whole instruction, which is four bytes for F000 NNNN
+                    if let Some(skipped) = decode(next) {
+                        pending.push(next.wrapping_add(skipped.size()));
+                    }
+                },
+                _ => pending.push(next),
+            }
+        }
+
+        //Instructions that overlap a later one cannot both be listed; keep the bytes as data
+        let mut items = BTreeMap::new();
+        let mut address = start;
+        while address < end {
+            let overlapped = |instruction: &Instruction| {
+                let following = code.range(address as u16 + 1..).next();
+                matches!(following, Some((&next, _)) if (next as usize) < address + instruction.size() as usize)
+            };
+            match code.get(&(address as u16)) {
+                Some(instruction) if !overlapped(instruction) => {
+                    items.insert(address as u16, Item::Code(*instruction));
+                    address += instruction.size() as usize;
+                },
+                _ => {
+                    items.insert(address as u16, Item::Data(memory[address]));
+                    address += 1;
+                },
+            }
+        }
+
+        //Only addresses that start a line can carry a label; others stay numeric
+        let mut labels: BTreeMap<u16, String> = targets.into_iter()
+            .filter(|(address, _)| items.contains_key(address))
+            .map(|(address, target)| {
+                let prefix = match target {
+                    Target::Data => "data",
+                    Target::Jump => "label",
+                    Target::Call => "sub",
+                };
+                (address, format!("{}-{:03X}", prefix, address))
+            })
+            .collect();
+        if items.contains_key(&config.load_address) {
+            labels.insert(config.load_address, String::from("main"));
+        }
+
+        Self { load_address: config.load_address, items, labels }
+    }
+
+    /// The instruction at `address` if control flow reaches it.
+    pub fn instruction_at(&self, address: u16) -> Option<Instruction> {
+        match self.items.get(&address) {
+            Some(Item::Code(instruction)) => Some(*instruction),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.labels.get(&address).map(String::as_str)
+    }
+
+    /// Addresses of all reachable instructions, in order.
+    pub fn code_addresses(&self) -> impl Iterator<Item = u16> + '_ {
+        self.items.iter()
+            .filter(|(_, item)| matches!(item, Item::Code(_)))
+            .map(|(address, _)| *address)
+    }
+
+    /// `instruction` in Octo syntax, with addresses replaced by their labels.
+    pub fn render(&self, instruction: &Instruction) -> String {
+        let label = match *instruction {
+            Instruction::Jp(target) | Instruction::JpV0(target) | Instruction::Call(target)
+            | Instruction::LdI(target) | Instruction::LdILong(target) => self.labels.get(&target),
+            _ => None,
+        };
+        match (instruction, label) {
+            (Instruction::Jp(_), Some(label)) => format!("jump {}", label),
+            (Instruction::JpV0(_), Some(label)) => format!("jump0 {}", label),
+            (Instruction::Call(_), Some(label)) => label.clone(),
+            (Instruction::LdI(_), Some(label)) => format!("i := {}", label),
+            (Instruction::LdILong(_), Some(label)) => format!("i := long {}", label),
+            _ => instruction.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.load_address != PC_INITIAL {
+            writeln!(f, ":org 0x{:03X}", self.load_address)?;
+        }
+        let data_starts: BTreeSet<u16> = self.labels.keys().copied().collect();
+        let mut data_line: Vec<u8> = Vec::with_capacity(BYTES_PER_LINE);
+        let mut pending_skip = false;
+        let first = self.items.keys().next().copied().unwrap_or(self.load_address);
+        for (&address, item) in &self.items {
+            let labelled = data_starts.contains(&address);
+            if !data_line.is_empty() && (labelled || !matches!(item, Item::Data(_)) || data_line.len() == BYTES_PER_LINE) {
+                write_bytes(f, &data_line)?;
+                data_line.clear();
+            }
+            if let Some(label) = self.labels.get(&address) {
+                if pending_skip {
+                    writeln!(f)?;
+                    pending_skip = false;
+                }
+                if address != first {
+                    writeln!(f)?;
+                }
+                writeln!(f, ": {}", label)?;
+            }
+            match item {
+                Item::Code(instruction) => {
+                    //The instruction a skip guards goes on the same line as its condition
+                    if pending_skip {
+                        write!(f, " {}", self.render(instruction))?;
+                    } else {
+                        write!(f, "\t{}", self.render(instruction))?;
+                    }
+                    pending_skip = instruction.is_skip() && !pending_skip;
+                    if !pending_skip {
+                        writeln!(f)?;
+                    }
+                },
+                Item::Data(byte) => {
+                    if pending_skip {
+                        writeln!(f)?;
+                        pending_skip = false;
+                    }
+                    data_line.push(*byte);
+                },
+            }
+        }
+        if pending_skip {
+            writeln!(f)?;
+        }
+        if !data_line.is_empty() {
+            write_bytes(f, &data_line)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_bytes(f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+    let bytes: Vec<String> = bytes.iter().map(|byte| format!("0x{:02X}", byte)).collect();
+    writeln!(f, "\t{}", bytes.join(" "))
+}
+
+/// The Octo listing of `rom` as `config` would load and run it.
+pub fn disassemble(rom: &[u8], config: &Config) -> String {
+    Disassembly::new(rom, config).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::*;
+    use crate::assembler::assemble;
+    use crate::chip8::Platform;
+
+    fn analyse(rom: &[u8], platform: Platform) -> Disassembly {
+        Disassembly::new(rom, &Config::new(platform))
+    }
+
+    #[test]
+    fn bytes_after_an_unconditional_jump_are_data() {
+        let rom = [
+            0x60, 0x01, //0x200 v0 := 1
+            0x12, 0x06, //0x202 jump 0x206
+            0xFF, 0xFF, //0x204 never reached
+            0x00, 0xE0, //0x206 clear
+            0x12, 0x06, //0x208 jump 0x206
+        ];
+        let disassembly = analyse(&rom, Platform::Chip8);
+        assert_eq!(disassembly.code_addresses().collect::<Vec<_>>(), [0x200, 0x202, 0x206, 0x208]);
+        assert_eq!(disassembly.instruction_at(0x204), None);
+        assert_eq!(disassembly.instruction_at(0x206), Some(Instruction::Cls));
+        assert_eq!(
+            disassembly.to_string(),
+            ": main\n\tv0 := 0x01\n\tjump label-206\n\t0xFF 0xFF\n\n: label-206\n\tclear\n\tjump label-206\n"
+        );
+    }
+
+    #[test]
+    fn labels_are_named_after_how_they_are_reached() {
+        let rom = [
+            0x22, 0x08, //0x200 call 0x208
+            0xA2, 0x0C, //0x202 i := 0x20C
+            0x12, 0x0A, //0x204 jump 0x20A
+            0x00, 0x00, //0x206 padding
+            0x00, 0xEE, //0x208 return
+            0x12, 0x08, //0x20A jump 0x208, already a call target
+            0x3C, 0x66, //0x20C sprite data
+        ];
+        let disassembly = analyse(&rom, Platform::Chip8);
+        assert_eq!(disassembly.label(0x200), Some("main"));
+        assert_eq!(disassembly.label(0x208), Some("sub-208"));
+        assert_eq!(disassembly.label(0x20A), Some("label-20A"));
+        assert_eq!(disassembly.label(0x20C), Some("data-20C"));
+        assert_eq!(disassembly.label(0x206), None);
+        assert_eq!(disassembly.render(&Instruction::Call(0x208)), "sub-208");
+        assert_eq!(disassembly.render(&Instruction::LdI(0x20C)), "i := data-20C");
+        assert_eq!(disassembly.render(&Instruction::Jp(0x300)), "jump 0x300");
+        assert_eq!(disassembly.instruction_at(0x20C), None);
+    }
+
+    #[test]
+    fn a_skip_steps_over_a_long_load_as_a_whole() {
+        let rom = [
+            0x30, 0x00, //0x200 if v0 != 0x00 then
+            0xF0, 0x00, 0x03, 0x00, //0x202 i := long 0x0300
+            0x00, 0xE0, //0x206 clear
+            0x12, 0x06, //0x208 jump 0x206
+        ];
+        let disassembly = analyse(&rom, Platform::XoChip);
+        assert_eq!(disassembly.code_addresses().collect::<Vec<_>>(), [0x200, 0x202, 0x206, 0x208]);
+        assert_eq!(disassembly.instruction_at(0x202), Some(Instruction::LdILong(0x300)));
+        assert!(disassembly.to_string().contains("\tif v0 != 0x00 then i := long 0x0300\n"));
+
+        //Without XO-CHIP the long load is data, and the skip's two outcomes land in it
+        let disassembly = analyse(&rom, Platform::Chip8);
+        assert_eq!(disassembly.code_addresses().collect::<Vec<_>>(), [0x200]);
+    }
+
+    #[test]
+    fn listings_assemble_back_to_the_original_rom() {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/roms");
+        let mut checked = 0;
+        for entry in fs::read_dir(directory).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension() != Some("ch8".as_ref()) {
+                continue;
+            }
+            let rom = fs::read(&path).unwrap();
+            for &platform in Platform::ALL.iter() {
+                let listing = disassemble(&rom, &Config::new(platform));
+                let assembled = assemble(&listing)
+                    .unwrap_or_else(|e| panic!("{} on {} does not assemble: {}\n{}", path.display(), platform, e, listing));
+                assert!(assembled == rom, "{} on {} assembles differently:\n{}", path.display(), platform, listing);
+            }
+            checked += 1;
+        }
+        assert_eq!(checked, 5);
+    }
+}
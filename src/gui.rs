@@ -1,24 +1,30 @@
-use iced::{button, slider,
+use iced::{button, pick_list, scrollable, slider,
            canvas::{Cache, Cursor, Fill, Geometry, Program},
-           Color, Element, Point, Rectangle, Size};
-use crate::application::{Chip8EmulatorSettings, Message};
+           Color, Element, Point, Rectangle, Size, Text};
+use tjr_chip8::chip8::{constants, FontSet, Platform, QuirksPreset, TimingMode};
+use crate::application::{Chip8EmulatorSettings, FastForward, Address, Message, SlowMotion};
+use crate::save_slots::SaveSlot;
 
 mod main_menu;
 mod emulation_screen;
+mod settings_menu;
+pub mod debugger;
+
+/// Background, plane 1, plane 2 and the colour where both XO-CHIP planes overlap.
+const DEFAULT_PALETTE: [[u32; 3]; 4] = [[0, 0, 0], [255, 255, 255], [170, 170, 170], [85, 85, 85]];
 
 pub struct Gui {
     pub current_page: PageModel,
     pub screen: Screen,
+    pub error: Option<String>,
 }
 
 pub struct Screen {
-    pub pixels: [bool; 2048],
-    pub bg_red: u32,
-    pub bg_green: u32,
-    pub bg_blue: u32,
-    pub fg_red: u32,
-    pub fg_green: u32,
-    pub fg_blue: u32,
+    pub pixels: Vec<u8>,
+    pub width: usize,
+    pub height: usize,
+    /// RGB colour for each plane bitmask a pixel can hold.
+    pub palette: [[u32; 3]; 4],
     pub screen: Cache,
 }
 
@@ -27,65 +33,138 @@ pub enum PageModel {
     MainMenu {
         clock_speed_state: slider::State,
         clock_speed_value: u32,
-        bg_red_state: slider::State,
-        bg_red_value: u32,
-        bg_green_state: slider::State,
-        bg_green_value: u32,
-        bg_blue_state: slider::State,
-        bg_blue_value: u32,
-        fg_red_state: slider::State,
-        fg_red_value: u32,
-        fg_green_state: slider::State,
-        fg_green_value: u32,
-        fg_blue_state: slider::State,
-        fg_blue_value: u32,
+        timing_state: pick_list::State<TimingMode>,
+        rewind_memory_state: slider::State,
+        fast_forward_state: pick_list::State<FastForward>,
+        palette_states: [[slider::State; 3]; 4],
+        platform_state: pick_list::State<Platform>,
+        memory_size_state: pick_list::State<usize>,
+        stack_depth_state: pick_list::State<usize>,
+        load_address_state: pick_list::State<Address>,
+        font_state: pick_list::State<FontSet>,
+        font_address_state: pick_list::State<Address>,
+        choose_font_button: button::State,
+        quirks_preset_state: pick_list::State<QuirksPreset>,
         choose_rom_button: button::State,
         launch_button: button::State,
     },
-    EmulationScreen,
+    EmulationScreen {
+        pause_button: button::State,
+        reset_button: button::State,
+        reload_button: button::State,
+        menu_button: button::State,
+        frame_advance_button: button::State,
+        slow_motion_state: pick_list::State<SlowMotion>,
+        slot_state: pick_list::State<SaveSlot>,
+        save_button: button::State,
+        load_button: button::State,
+        debug_button: button::State,
+    },
+    Debugger {
+        step_button: button::State,
+        step_over_button: button::State,
+        step_out_button: button::State,
+        run_to_cursor_button: button::State,
+        continue_button: button::State,
+        menu_button: button::State,
+        line_buttons: [button::State; debugger::DISASSEMBLY_LINES],
+        disassembly_scroll: scrollable::State,
+        memory_scroll: scrollable::State,
+        previous_page_button: button::State,
+        next_page_button: button::State,
+        pc_page_button: button::State,
+        i_page_button: button::State,
+        analysis: debugger::Analysis,
+    },
+}
+
+impl PageModel {
+    pub fn main_menu(clock_speed_value: u32) -> Self {
+        PageModel::MainMenu {
+            clock_speed_state: slider::State::new(),
+            clock_speed_value,
+            timing_state: pick_list::State::default(),
+            rewind_memory_state: slider::State::new(),
+            fast_forward_state: pick_list::State::default(),
+            palette_states: [[slider::State::new(); 3]; 4],
+            platform_state: pick_list::State::default(),
+            memory_size_state: pick_list::State::default(),
+            stack_depth_state: pick_list::State::default(),
+            load_address_state: pick_list::State::default(),
+            font_state: pick_list::State::default(),
+            font_address_state: pick_list::State::default(),
+            choose_font_button: button::State::new(),
+            quirks_preset_state: pick_list::State::default(),
+            choose_rom_button: button::State::new(),
+            launch_button: button::State::new(),
+        }
+    }
+
+    pub fn emulation_screen() -> Self {
+        PageModel::EmulationScreen {
+            pause_button: button::State::new(),
+            reset_button: button::State::new(),
+            reload_button: button::State::new(),
+            menu_button: button::State::new(),
+            frame_advance_button: button::State::new(),
+            slow_motion_state: pick_list::State::default(),
+            slot_state: pick_list::State::default(),
+            save_button: button::State::new(),
+            load_button: button::State::new(),
+            debug_button: button::State::new(),
+        }
+    }
+
+    pub fn debugger() -> Self {
+        PageModel::Debugger {
+            step_button: button::State::new(),
+            step_over_button: button::State::new(),
+            step_out_button: button::State::new(),
+            run_to_cursor_button: button::State::new(),
+            continue_button: button::State::new(),
+            menu_button: button::State::new(),
+            line_buttons: Default::default(),
+            disassembly_scroll: scrollable::State::new(),
+            memory_scroll: scrollable::State::new(),
+            previous_page_button: button::State::new(),
+            next_page_button: button::State::new(),
+            pc_page_button: button::State::new(),
+            i_page_button: button::State::new(),
+            analysis: debugger::Analysis::default(),
+        }
+    }
 }
 
 impl Gui {
     pub fn new() -> Self {
         Self {
-            current_page: PageModel::MainMenu {
-                clock_speed_state: slider::State::new(),
-                clock_speed_value: 800,
-                bg_red_state: slider::State::new(),
-                bg_red_value: 0,
-                bg_green_state: slider::State::new(),
-                bg_green_value: 0,
-                bg_blue_state: slider::State::new(),
-                bg_blue_value: 0,
-                fg_red_state: slider::State::new(),
-                fg_red_value: 0,
-                fg_green_state: slider::State::new(),
-                fg_green_value: 0,
-                fg_blue_state: slider::State::new(),
-                fg_blue_value: 0,
-                choose_rom_button: button::State::new(),
-                launch_button: button::State::new(),
-            },
+            current_page: PageModel::main_menu(800),
             screen: Screen {
-                pixels: [false; 2048],
-                bg_red: 0,
-                bg_green: 0,
-                bg_blue: 0,
-                fg_red: 0,
-                fg_green: 0,
-                fg_blue: 0,
+                pixels: vec![0; (constants::SCREEN_WIDTH * constants::SCREEN_HEIGHT) as usize],
+                width: constants::SCREEN_WIDTH as usize,
+                height: constants::SCREEN_HEIGHT as usize,
+                palette: DEFAULT_PALETTE,
                 screen: Cache::new(),
-            }
+            },
+            error: None,
         }
     }
 
-    pub fn make(&mut self, settings: &Chip8EmulatorSettings) -> Element<Message> {
+    pub fn make(&mut self, settings: &Chip8EmulatorSettings, paused: bool, slow_motion: SlowMotion, slots: &[SaveSlot], selected_slot: usize, session: Option<debugger::Session>) -> Element<Message> {
         match &mut self.current_page {
-            PageModel::MainMenu { clock_speed_state, clock_speed_value: _, bg_red_state, bg_red_value: _, bg_green_state, bg_green_value: _, bg_blue_state, bg_blue_value: _, fg_red_state, fg_red_value: _, fg_green_state, fg_green_value: _, fg_blue_state, fg_blue_value: _, choose_rom_button, launch_button } => {
-                main_menu::draw(settings.rom_name.to_string() , clock_speed_state, settings.clock_speed as i32, bg_red_state, self.screen.bg_red as i32, bg_green_state, self.screen.bg_green as i32, bg_blue_state, self.screen.bg_blue as i32, fg_red_state, self.screen.fg_red as i32, fg_green_state, self.screen.fg_green as i32, fg_blue_state, self.screen.fg_blue as i32, choose_rom_button, launch_button)
+            PageModel::MainMenu { clock_speed_state, clock_speed_value: _, timing_state, rewind_memory_state, fast_forward_state, palette_states, platform_state, memory_size_state, stack_depth_state, load_address_state, font_state, font_address_state, choose_font_button, quirks_preset_state, choose_rom_button, launch_button } => {
+                main_menu::draw(settings.rom_name.to_string(), settings.rom_info.clone(), self.error.clone(), clock_speed_state, settings.clock_speed as i32, timing_state, settings.timing, rewind_memory_state, settings.rewind_memory_mb as i32, fast_forward_state, settings.fast_forward, palette_states, self.screen.palette, platform_state, main_menu::MachinePickers { memory_size_state, stack_depth_state, load_address_state, font_state, font_address_state, choose_font_button }, quirks_preset_state, settings.config, choose_rom_button, launch_button)
+            },
+            PageModel::EmulationScreen { pause_button, reset_button, reload_button, menu_button, frame_advance_button, slow_motion_state, slot_state, save_button, load_button, debug_button } => {
+                let controls = emulation_screen::Controls { paused, slow_motion, pause_button, reset_button, reload_button, menu_button, frame_advance_button, slow_motion_state, debug_button };
+                emulation_screen::draw(&mut self.screen, settings.rom_name.to_string(), self.error.clone(), controls, slot_state, slots.to_vec(), selected_slot, save_button, load_button)
             },
-            PageModel::EmulationScreen => {
-                emulation_screen::draw(&mut self.screen, settings.rom_name.to_string())
+            PageModel::Debugger { step_button, step_over_button, step_out_button, run_to_cursor_button, continue_button, menu_button, line_buttons, disassembly_scroll, memory_scroll, previous_page_button, next_page_button, pc_page_button, i_page_button, analysis } => {
+                let controls = debugger::Controls { step_button, step_over_button, step_out_button, run_to_cursor_button, continue_button, menu_button, line_buttons, disassembly_scroll, memory_scroll, previous_page_button, next_page_button, pc_page_button, i_page_button, analysis };
+                match session {
+                    Some(session) => debugger::draw(format!("Debugger - {}", settings.rom_name), self.error.clone(), session, controls),
+                    None => Text::new("No ROM is running").into(),
+                }
             }
         }
     }
@@ -94,17 +173,18 @@ impl Gui {
 
 impl Program<Message> for Screen {
     fn draw(&self, bounds: Rectangle, _cursor: Cursor) -> Vec<Geometry> {
-        let bg_color = Color::from_rgba(self.bg_red as f32 / 256.0 , self.bg_green as f32 / 256.0, self.bg_blue as f32 / 256.0, 1.0);
-        let fg_color = Color::from_rgba(self.fg_red as f32 / 256.0 , self.fg_green as f32 / 256.0, self.fg_blue as f32 / 256.0, 1.0);
+        let mut colors = [Color::BLACK; 4];
+        for (color, rgb) in colors.iter_mut().zip(self.palette.iter()) {
+            *color = Color::from_rgb8(rgb[0] as u8, rgb[1] as u8, rgb[2] as u8);
+        }
+        //The canvas is a fixed 640x320, so hi-res pixels are drawn at half the size of lo-res ones
+        let pixel_size = bounds.width / self.width as f32;
         let geo = self.screen.draw(bounds.size(), |frame| {
-            for i in 0..2048 {
-                let mut color = bg_color;
-                if self.pixels[(i%64) + (64 * (i/64))] {
-                    color = fg_color;
-                }
+            for i in 0..(self.width * self.height) {
+                let color = colors[(self.pixels[i] & 0x3) as usize];
                 frame.fill_rectangle(
-                    Point::new((i % 64) as f32 * 10.0, (i / 64) as f32 * 10.0),
-                    Size::new(10.0, 10.0),
+                    Point::new((i % self.width) as f32 * pixel_size, (i / self.width) as f32 * pixel_size),
+                    Size::new(pixel_size, pixel_size),
                     Fill::from(color)
                 );
             }
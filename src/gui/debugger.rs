@@ -0,0 +1,282 @@
+use iced::{button, scrollable, Align, Button, Color, Column, Container, Element, Length, Row, Scrollable, Text};
+use tjr_chip8::chip8::{Chip8, Config, CpuStatus, Instruction};
+use tjr_chip8::disassembler::Disassembly;
+use crate::application::Message;
+use super::PageModel;
+
+/// Instructions listed around `pc`, and how many of them come before it.
+pub const DISASSEMBLY_LINES: usize = 24;
+const LINES_BEFORE_PC: usize = 8;
+const HEX_BYTES_PER_ROW: usize = 16;
+/// Hex rows on one page of the memory view. Laying out all 4096 rows of XO-CHIP memory on every
+/// redraw is too slow, so the view shows one page at a time.
+const HEX_PAGE_ROWS: usize = 64;
+const HEX_PAGE_BYTES: usize = HEX_PAGE_ROWS * HEX_BYTES_PER_ROW;
+/// Hex rows shown around `pc` and around `i`, and how many of them come before the row holding
+/// the address.
+const HEX_WINDOW_ROWS: usize = 8;
+const HEX_ROWS_BEFORE: usize = 2;
+const PC_COLOR: Color = Color { r: 0.0, g: 0.35, b: 0.8, a: 1.0 };
+const CURSOR_COLOR: Color = Color { r: 0.8, g: 0.45, b: 0.0, a: 1.0 };
+
+/// The interpreter being debugged and what the debugger is doing with it.
+pub struct Session<'a> {
+    pub chip8: &'a Chip8,
+    /// The address run to cursor stops at, picked by clicking a disassembly line.
+    pub cursor: Option<u16>,
+    /// A step over, step out or run to cursor is still executing.
+    pub running: bool,
+    /// The page of memory shown in the hex view.
+    pub memory_page: usize,
+}
+
+/// State for the buttons and scroll areas of the page.
+pub struct Controls<'a> {
+    pub step_button: &'a mut button::State,
+    pub step_over_button: &'a mut button::State,
+    pub step_out_button: &'a mut button::State,
+    pub run_to_cursor_button: &'a mut button::State,
+    pub continue_button: &'a mut button::State,
+    pub menu_button: &'a mut button::State,
+    pub line_buttons: &'a mut [button::State; DISASSEMBLY_LINES],
+    pub disassembly_scroll: &'a mut scrollable::State,
+    pub memory_scroll: &'a mut scrollable::State,
+    pub previous_page_button: &'a mut button::State,
+    pub next_page_button: &'a mut button::State,
+    pub pc_page_button: &'a mut button::State,
+    pub i_page_button: &'a mut button::State,
+    pub analysis: &'a mut Analysis,
+}
+
+/// The control-flow analysis the listing is drawn from. Analysing all of memory is too slow to
+/// repeat on every redraw while the program runs, so it is only redone when memory or the
+/// configuration has changed since the last one.
+#[derive(Debug, Clone, Default)]
+pub struct Analysis {
+    memory: Vec<u8>,
+    config: Option<Config>,
+    disassembly: Option<Disassembly>,
+}
+
+impl Analysis {
+    fn of(&mut self, chip8: &Chip8) -> &Disassembly {
+        let config = *chip8.config();
+        let memory = chip8.memory();
+        let stale = self.config != Some(config) || self.memory.as_slice() != memory;
+        if stale || self.disassembly.is_none() {
+            self.memory.clear();
+            self.memory.extend_from_slice(memory);
+            self.config = Some(config);
+            self.disassembly = Some(Disassembly::new(&memory[config.load_address as usize..], &config));
+        }
+        self.disassembly.as_ref().unwrap()
+    }
+}
+
+/// One instruction or data byte of the listing around `pc`.
+struct Line {
+    address: u16,
+    label: Option<String>,
+    bytes: String,
+    text: String,
+}
+
+/// The listing around `pc`. Control-flow analysis of the current memory names labels and keeps
+/// data out of the way; if `pc` is somewhere the analysis did not reach, such as code written
+/// at run time, the listing is decoded straight from `pc` instead.
+fn listing(chip8: &Chip8, analysis: &mut Analysis) -> Vec<Line> {
+    let memory = chip8.memory();
+    let disassembly = analysis.of(chip8);
+    let pc = chip8.pc();
+    let analysed: Vec<u16> = disassembly.code_addresses().collect();
+    let addresses = match analysed.iter().position(|&address| address == pc) {
+        Some(position) => analysed[position.saturating_sub(LINES_BEFORE_PC)..].to_vec(),
+        None => {
+            let mut addresses = Vec::with_capacity(DISASSEMBLY_LINES);
+            let mut address = pc as usize;
+            while addresses.len() < DISASSEMBLY_LINES && address < memory.len() {
+                addresses.push(address as u16);
+                address += Instruction::read(memory, address).map_or(1, |instruction| instruction.size() as usize);
+            }
+            addresses
+        },
+    };
+    addresses.into_iter().take(DISASSEMBLY_LINES).map(|address| {
+        let decoded = disassembly.instruction_at(address).map(|instruction| (instruction, disassembly.render(&instruction)))
+            .or_else(|| Instruction::read(memory, address as usize).ok().map(|instruction| (instruction, instruction.to_string())));
+        let (size, text) = match decoded {
+            Some((instruction, text)) => (instruction.size() as usize, text),
+            None => (1, format!("0x{:02X}", memory[address as usize])),
+        };
+        let end = (address as usize + size).min(memory.len());
+        let bytes: Vec<String> = memory[address as usize..end].iter().map(|byte| format!("{:02X}", byte)).collect();
+        Line { address, label: disassembly.label(address).map(String::from), bytes: bytes.concat(), text }
+    }).collect()
+}
+
+fn status(session: &Session) -> String {
+    let pc = session.chip8.pc();
+    match session.chip8.status {
+        _ if session.running => String::from("Running..."),
+        CpuStatus::Halted => format!("Halted at 0x{:03X}", pc),
+        CpuStatus::Exited => String::from("Program exited"),
+        CpuStatus::AwaitingKeyPress { x } | CpuStatus::AwaitingKeyRelease { x, .. } => format!("Waiting for a key for V{:X}", x),
+        _ => format!("Paused at 0x{:03X}", pc),
+    }
+}
+
+fn registers<'a>(chip8: &Chip8) -> Column<'a, Message> {
+    let mut column = Column::new().spacing(4).push(Text::new("Registers").size(20));
+    for (index, values) in chip8.registers().chunks(4).enumerate() {
+        let cells: Vec<String> = values.iter().enumerate()
+            .map(|(offset, value)| format!("V{:X} {:02X}", index * 4 + offset, value))
+            .collect();
+        column = column.push(Text::new(cells.join("   ")).size(16));
+    }
+    let stack: Vec<String> = chip8.stack().iter().rev().map(|address| format!("0x{:03X}", address)).collect();
+    column
+        .push(Text::new(format!("I  0x{:03X}   PC 0x{:03X}   SP {}", chip8.index(), chip8.pc(), chip8.stack().len())).size(16))
+        .push(Text::new(format!("Delay {:02X}   Sound {:02X}", chip8.delay_timer(), chip8.sound_timer())).size(16))
+        .push(Text::new("Stack, newest first").size(20))
+        .push(Text::new(if stack.is_empty() { String::from("(empty)") } else { stack.join("\n") }).size(16))
+}
+
+/// `count` hex rows of `memory` starting with row `first`.
+fn hex_rows(memory: &[u8], first: usize, count: usize) -> String {
+    let rows: Vec<String> = memory.chunks(HEX_BYTES_PER_ROW).enumerate().skip(first).take(count).map(|(row, bytes)| {
+        let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        format!("{:04X}  {}", row * HEX_BYTES_PER_ROW, bytes.join(" "))
+    }).collect();
+    rows.join("\n")
+}
+
+/// The hex rows of `memory` around `address`.
+fn hex_window(memory: &[u8], address: u16) -> String {
+    let total_rows = (memory.len() + HEX_BYTES_PER_ROW - 1) / HEX_BYTES_PER_ROW;
+    let first = (address as usize / HEX_BYTES_PER_ROW)
+        .saturating_sub(HEX_ROWS_BEFORE)
+        .min(total_rows.saturating_sub(HEX_WINDOW_ROWS));
+    hex_rows(memory, first, HEX_WINDOW_ROWS)
+}
+
+/// The page of the memory view holding `address`.
+pub fn memory_page(address: u16) -> usize {
+    address as usize / HEX_PAGE_BYTES
+}
+
+pub fn draw<'a>(title: String,
+                error: Option<String>,
+                session: Session,
+                controls: Controls<'a>) -> Element<'a, Message> {
+    let chip8 = session.chip8;
+    let can_step = !session.running && matches!(chip8.status, CpuStatus::Paused);
+    let mut step = Button::new(controls.step_button, Text::new("Step (F10)"));
+    let mut step_over = Button::new(controls.step_over_button, Text::new("Step over (F11)"));
+    let mut step_out = Button::new(controls.step_out_button, Text::new("Step out (Shift+F11)"));
+    let mut run_to_cursor = Button::new(controls.run_to_cursor_button, Text::new("Run to cursor"));
+    if can_step {
+        step = step.on_press(Message::DebugStep);
+        step_over = step_over.on_press(Message::DebugStepOver);
+        if !chip8.stack().is_empty() {
+            step_out = step_out.on_press(Message::DebugStepOut);
+        }
+        if session.cursor.is_some() {
+            run_to_cursor = run_to_cursor.on_press(Message::DebugRunToCursor);
+        }
+    }
+    let buttons = Row::new()
+        .spacing(10)
+        .align_items(Align::Center)
+        .push(step)
+        .push(step_over)
+        .push(step_out)
+        .push(run_to_cursor)
+        .push(
+            Button::new(controls.continue_button, Text::new("Continue (F5)")).on_press(Message::DebugContinue)
+        )
+        .push(
+            Button::new(controls.menu_button, Text::new("Menu (Esc)")).on_press(Message::Goto(PageModel::main_menu(0)))
+        );
+
+    let mut disassembly = Scrollable::new(controls.disassembly_scroll)
+        .spacing(2)
+        .width(Length::Units(360))
+        .height(Length::Units(480))
+        .push(Text::new("Disassembly (click to set the cursor)").size(20));
+    for (line, button_state) in listing(chip8, controls.analysis).into_iter().zip(controls.line_buttons.iter_mut()) {
+        if let Some(label) = line.label {
+            disassembly = disassembly.push(Text::new(format!(": {}", label)).size(16));
+        }
+        let marker = if line.address == chip8.pc() { ">" } else if Some(line.address) == session.cursor { "*" } else { " " };
+        let mut text = Text::new(format!("{} {:03X}  {:<8} {}", marker, line.address, line.bytes, line.text)).size(16);
+        if line.address == chip8.pc() {
+            text = text.color(PC_COLOR);
+        } else if Some(line.address) == session.cursor {
+            text = text.color(CURSOR_COLOR);
+        }
+        disassembly = disassembly.push(
+            Button::new(button_state, text).padding(2).width(Length::Fill).on_press(Message::DebugCursorSelected(line.address))
+        );
+    }
+
+    let mem = chip8.memory();
+    let pages = (mem.len() + HEX_PAGE_BYTES - 1) / HEX_PAGE_BYTES;
+    let page = session.memory_page.min(pages - 1);
+    let page_end = ((page + 1) * HEX_PAGE_BYTES).min(mem.len());
+    let mut previous_page = Button::new(controls.previous_page_button, Text::new("<"));
+    let mut next_page = Button::new(controls.next_page_button, Text::new(">"));
+    if page > 0 {
+        previous_page = previous_page.on_press(Message::DebugMemoryPage(page - 1));
+    }
+    if page + 1 < pages {
+        next_page = next_page.on_press(Message::DebugMemoryPage(page + 1));
+    }
+    let page_buttons = Row::new()
+        .spacing(10)
+        .align_items(Align::Center)
+        .push(previous_page)
+        .push(Text::new(format!("Page {} of {}", page + 1, pages)).size(16))
+        .push(next_page)
+        .push(
+            Button::new(controls.pc_page_button, Text::new("PC")).on_press(Message::DebugMemoryPage(memory_page(chip8.pc())))
+        )
+        .push(
+            Button::new(controls.i_page_button, Text::new("I")).on_press(Message::DebugMemoryPage(memory_page(chip8.index())))
+        );
+    let memory = Scrollable::new(controls.memory_scroll)
+        .width(Length::Units(480))
+        .height(Length::Units(480))
+        .spacing(4)
+        .push(Text::new(format!("Memory 0x{:04X}-0x{:04X}", page * HEX_PAGE_BYTES, page_end - 1)).size(20))
+        .push(page_buttons)
+        .push(Text::new(hex_rows(mem, page * HEX_PAGE_ROWS, HEX_PAGE_ROWS)).size(14))
+        .push(Text::new(format!("Memory at PC (0x{:03X})", chip8.pc())).size(20))
+        .push(Text::new(hex_window(chip8.memory(), chip8.pc())).size(14))
+        .push(Text::new(format!("Memory at I (0x{:03X})", chip8.index())).size(20))
+        .push(Text::new(hex_window(chip8.memory(), chip8.index())).size(14));
+
+    let mut content = Column::new()
+        .align_items(Align::Center)
+        .spacing(10)
+        .push(Text::new(title))
+        .push(buttons)
+        .push(Text::new(status(&session)))
+        .push(
+            Row::new()
+                .spacing(20)
+                .push(registers(chip8).width(Length::Units(260)))
+                .push(disassembly)
+                .push(memory)
+        );
+    if let Some(message) = error {
+        content = content.push(
+            Text::new(message).color(Color::from_rgb(0.8, 0.0, 0.0))
+        );
+    }
+    Container::new(content)
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .center_x()
+        .center_y()
+        .into()
+}
@@ -1,20 +1,102 @@
-use iced::{Align, Canvas, Column, Container, Element, Length, Text};
-use crate::application::Message;
-use super::{Screen};
+use iced::{button, pick_list, Align, Button, Canvas, Color, Column, Container, Element, Length, PickList, Row, Text};
+use crate::application::{Message, SlowMotion};
+use crate::save_slots::SaveSlot;
+use super::{PageModel, Screen};
 
-pub fn draw<'a>(screen: &'a mut Screen, title: String) -> Element<'a , Message> {
-    let content = Column::new()
+/// State for the control bar above the screen.
+pub struct Controls<'a> {
+    pub paused: bool,
+    pub slow_motion: SlowMotion,
+    pub pause_button: &'a mut button::State,
+    pub reset_button: &'a mut button::State,
+    pub reload_button: &'a mut button::State,
+    pub menu_button: &'a mut button::State,
+    pub frame_advance_button: &'a mut button::State,
+    pub slow_motion_state: &'a mut pick_list::State<SlowMotion>,
+    pub debug_button: &'a mut button::State,
+}
+
+fn control_bar<'a>(controls: Controls<'a>) -> Row<'a, Message> {
+    let pause_label = if controls.paused { "Resume (P)" } else { "Pause (P)" };
+    let mut frame_advance = Button::new(controls.frame_advance_button, Text::new("Frame (F8)"));
+    if controls.paused {
+        frame_advance = frame_advance.on_press(Message::FrameAdvance);
+    }
+    Row::new()
+        .spacing(10)
+        .align_items(Align::Center)
+        .push(
+            Button::new(controls.pause_button, Text::new(pause_label)).on_press(Message::TogglePause)
+        )
+        .push(frame_advance)
+        .push(
+            PickList::new(controls.slow_motion_state, &SlowMotion::ALL[..], Some(controls.slow_motion), Message::SlowMotionSelected)
+        )
+        .push(
+            Button::new(controls.reset_button, Text::new("Reset (F6)")).on_press(Message::Reset)
+        )
+        .push(
+            Button::new(controls.reload_button, Text::new("Reload (F7)")).on_press(Message::Reload)
+        )
+        .push(
+            Button::new(controls.debug_button, Text::new("Debug (F12)")).on_press(Message::Goto(PageModel::debugger()))
+        )
+        .push(
+            Button::new(controls.menu_button, Text::new("Menu (Esc)")).on_press(Message::Goto(PageModel::main_menu(0)))
+        )
+}
+
+pub fn draw<'a>(screen: &'a mut Screen,
+                title: String,
+                error: Option<String>,
+                controls: Controls<'a>,
+                slot_picker: &'a mut pick_list::State<SaveSlot>,
+                slots: Vec<SaveSlot>,
+                selected_slot: usize,
+                save_button: &'a mut button::State,
+                load_button: &'a mut button::State) -> Element<'a , Message> {
+    let selected = slots.get(selected_slot).cloned();
+    let filled = selected.as_ref().map_or(false, |slot| slot.written.is_some());
+    let mut load = Button::new(load_button, Text::new(String::from("Load")));
+    if filled {
+        load = load.on_press(Message::LoadState);
+    }
+    let mut content = Column::new()
         .align_items(Align::Center)
+        .spacing(10)
         .push(
             Text::new(String::from(title))
         )
+        .push(
+            control_bar(controls)
+        )
         .push(
             Canvas::new(screen).width(Length::Units(640)).height(Length::Units(320))
+        )
+        .push(
+            Row::new()
+                .spacing(10)
+                .align_items(Align::Center)
+                .push(
+                    PickList::new(slot_picker, slots, selected, Message::SlotSelected)
+                )
+                .push(
+                    Button::new(save_button, Text::new(String::from("Save"))).on_press(Message::SaveState)
+                )
+                .push(load)
+        )
+        .push(
+            Text::new("F1-F4: quick-load slot, Shift+F1-F4: quick-save slot, hold Backspace: rewind, hold Tab or toggle `: fast-forward").size(16)
+        );
+    if let Some(message) = error {
+        content = content.push(
+            Text::new(message).color(Color::from_rgb(0.8, 0.0, 0.0))
         );
+    }
     Container::new(content)
         .width(Length::Fill)
         .height(Length::Fill)
         .center_x()
         .center_y()
         .into()
-}
\ No newline at end of file
+}
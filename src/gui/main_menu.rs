@@ -1,24 +1,46 @@
-use iced::{button, slider, Align, Button, Color, Column, Container, Element, Length, Row, Rule, Slider, Text, VerticalAlignment};
-use crate::application::Message;
-use super::PageModel;
+use iced::{button, pick_list, slider, Align, Button, Checkbox, Color, Column, Container, Element, Length, PickList, Row, Rule, Slider, Text};
+use tjr_chip8::chip8::{Config, FontSet, Platform, QuirksPreset, TimingMode};
+use tjr_chip8::database::RomInfo;
+use crate::application::{FastForward, Address, Message, MAX_CLOCK_SPEED, MIN_CLOCK_SPEED};
+use super::{settings_menu, PageModel};
+
+const MEMORY_SIZES: [usize; 6] = [2048, 4096, 8192, 16384, 32768, 65536];
+const STACK_DEPTHS: [usize; 3] = [12, 16, 32];
+
+/// State for the memory, stack, load address and font pickers.
+pub struct MachinePickers<'a> {
+    pub memory_size_state: &'a mut pick_list::State<usize>,
+    pub stack_depth_state: &'a mut pick_list::State<usize>,
+    pub load_address_state: &'a mut pick_list::State<Address>,
+    pub font_state: &'a mut pick_list::State<FontSet>,
+    pub font_address_state: &'a mut pick_list::State<Address>,
+    pub choose_font_button: &'a mut button::State,
+}
 
 pub fn draw<'a>(rom_name: String,
+                rom_info: Option<RomInfo>,
+                error: Option<String>,
                 clock_speed_slider: &'a mut slider::State,
                 clock_speed_value: i32,
-                bg_red_slider: &'a mut slider::State,
-                bg_red_value: i32,
-                bg_green_slider: &'a mut slider::State,
-                bg_green_value: i32,
-                bg_blue_slider: &'a mut slider::State,
-                bg_blue_value: i32,
-                fg_red_slider: &'a mut slider::State,
-                fg_red_value: i32,
-                fg_green_slider: &'a mut slider::State,
-                fg_green_value: i32,
-                fg_blue_slider: &'a mut slider::State,
-                fg_blue_value: i32,
+                timing_picker: &'a mut pick_list::State<TimingMode>,
+                timing: TimingMode,
+                rewind_memory_slider: &'a mut slider::State,
+                rewind_memory_value: i32,
+                fast_forward_picker: &'a mut pick_list::State<FastForward>,
+                fast_forward: FastForward,
+                palette_sliders: &'a mut [[slider::State; 3]; 4],
+                palette: [[u32; 3]; 4],
+                platform_picker: &'a mut pick_list::State<Platform>,
+                machine_pickers: MachinePickers<'a>,
+                quirks_preset_picker: &'a mut pick_list::State<QuirksPreset>,
+                config: Config,
                 choose_rom_button: &'a mut button::State,
                 launch_button: &'a mut button::State) -> Element<'a , Message> {
+    //A loaded custom font is offered alongside the built-in ones so it can be picked again
+    let mut fonts = FontSet::BUILT_IN.to_vec();
+    if let FontSet::Custom(_) = config.font {
+        fonts.push(config.font);
+    }
     let content = Column::new()
         .align_items(Align::Center)
         .push(
@@ -35,6 +57,12 @@ pub fn draw<'a>(rom_name: String,
             .push(
                 Text::new(rom_name.to_string()).size(20).width(Length::Shrink).height(Length::Units(25)),
             )
+            .push(
+                rom_details(rom_info)
+            )
+            .push(
+                Text::new(error.unwrap_or_default()).color(Color::from_rgb(0.8, 0.0, 0.0))
+            )
         )
         .push(
             Rule::horizontal(20)
@@ -54,7 +82,7 @@ pub fn draw<'a>(rom_name: String,
                             Text::new("Clock Speed (Hz)").size(20)
                         )
                         .push(
-                                Slider::new(clock_speed_slider, 500..=1000, clock_speed_value, Message::ClockSpeedChanged).width(Length::Units(100))
+                                Slider::new(clock_speed_slider, MIN_CLOCK_SPEED as i32..=MAX_CLOCK_SPEED as i32, clock_speed_value, Message::ClockSpeedChanged).width(Length::Units(200))
                         )
                         .push(
                             Text::new(clock_speed_value.to_string()).size(20)
@@ -65,41 +93,91 @@ pub fn draw<'a>(rom_name: String,
                         .spacing(20)
                         .align_items(Align::Center)
                         .push(
-                            Text::new("Background Color").color(Color::from_rgba(bg_red_value as f32 / 256.0 , bg_green_value as f32 / 256.0, bg_blue_value as f32 / 256.0, 1.0))
-                                .vertical_alignment(VerticalAlignment::Center)
-                        )
-                        .push(
-                            Column::new()
-                                .push(
-                                    Row::new()
-                                        .spacing(15)
-                                        .push(
-                                            Text::new("R")
-                                        )
-                                        .push(
-                                            Slider::new(bg_red_slider, 0..=255, bg_red_value, Message::BgRedChanged).width(Length::Units(80))
-                                        )
-                                )
-                                .push(
-                                    Row::new()
-                                        .spacing(15)
-                                        .push(
-                                            Text::new("G")
-                                        )
-                                        .push(
-                                            Slider::new(bg_green_slider, 0..=255, bg_green_value, Message::BgGreenChanged).width(Length::Units(80))
-                                        )
-                                )
-                                .push(
-                                    Row::new()
-                                        .spacing(15)
-                                        .push(
-                                            Text::new("B")
-                                        )
-                                        .push(
-                                            Slider::new(bg_blue_slider, 0..=255, bg_blue_value, Message::BgBlueChanged).width(Length::Units(80))
-                                        )
-                                )
+                            Text::new("Timing").size(20)
+                        )
+                        .push(
+                            PickList::new(timing_picker, &TimingMode::ALL[..], Some(timing), Message::TimingModeSelected)
+                        )
+                )
+                .push(
+                    Row::new()
+                        .spacing(20)
+                        .push(
+                            Text::new("Rewind Memory (MB)").size(20)
+                        )
+                        .push(
+                            Slider::new(rewind_memory_slider, 0..=64, rewind_memory_value, Message::RewindMemoryChanged).width(Length::Units(100))
+                        )
+                        .push(
+                            Text::new(rewind_memory_value.to_string()).size(20)
+                        )
+                )
+                .push(
+                    Row::new()
+                        .spacing(20)
+                        .align_items(Align::Center)
+                        .push(
+                            Text::new("Fast-forward").size(20)
+                        )
+                        .push(
+                            PickList::new(fast_forward_picker, &FastForward::ALL[..], Some(fast_forward), Message::FastForwardSelected)
+                        )
+                )
+                .push(
+                    settings_menu::palette_picker(palette_sliders, palette)
+                )
+                .push(
+                    Row::new()
+                        .spacing(20)
+                        .align_items(Align::Center)
+                        .push(
+                            Text::new("Platform").size(20)
+                        )
+                        .push(
+                            PickList::new(platform_picker, &Platform::ALL[..], Some(config.platform), Message::PlatformSelected)
+                        )
+                )
+                .push(
+                    Row::new()
+                        .spacing(10)
+                        .align_items(Align::Center)
+                        .push(
+                            Text::new("Memory").size(20)
+                        )
+                        .push(
+                            PickList::new(machine_pickers.memory_size_state, &MEMORY_SIZES[..], Some(config.memory_size), Message::MemorySizeSelected)
+                        )
+                        .push(
+                            Text::new("Stack").size(20)
+                        )
+                        .push(
+                            PickList::new(machine_pickers.stack_depth_state, &STACK_DEPTHS[..], Some(config.stack_depth), Message::StackDepthSelected)
+                        )
+                        .push(
+                            Text::new("Load at").size(20)
+                        )
+                        .push(
+                            PickList::new(machine_pickers.load_address_state, &Address::LOAD_ADDRESSES[..], Some(Address(config.load_address)), Message::LoadAddressSelected)
+                        )
+                )
+                .push(
+                    Row::new()
+                        .spacing(10)
+                        .align_items(Align::Center)
+                        .push(
+                            Text::new("Font").size(20)
+                        )
+                        .push(
+                            PickList::new(machine_pickers.font_state, fonts, Some(config.font), Message::FontSelected)
+                        )
+                        .push(
+                            Button::new(machine_pickers.choose_font_button, Text::new(String::from("Load Font"))).on_press(Message::ChooseFont)
+                        )
+                        .push(
+                            Text::new("at").size(20)
+                        )
+                        .push(
+                            PickList::new(machine_pickers.font_address_state, &Address::FONT_ADDRESSES[..], Some(Address(config.font_address)), Message::FontAddressSelected)
                         )
                 )
                 .push(
@@ -107,41 +185,29 @@ pub fn draw<'a>(rom_name: String,
                         .spacing(20)
                         .align_items(Align::Center)
                         .push(
-                            Text::new("Foreground Color").color(Color::from_rgba(fg_red_value as f32 / 256.0 , fg_green_value as f32 / 256.0, fg_blue_value as f32 / 256.0, 1.0))
-                                .vertical_alignment(VerticalAlignment::Center)
-                        )
-                        .push(
-                            Column::new()
-                                .push(
-                                    Row::new()
-                                        .spacing(15)
-                                        .push(
-                                            Text::new("R")
-                                        )
-                                        .push(
-                                            Slider::new(fg_red_slider, 0..=255, fg_red_value, Message::FgRedChanged).width(Length::Units(80))
-                                        )
-                                )
-                                .push(
-                                    Row::new()
-                                        .spacing(15)
-                                        .push(
-                                            Text::new("G")
-                                        )
-                                        .push(
-                                            Slider::new(fg_green_slider, 0..=255, fg_green_value, Message::FgGreenChanged).width(Length::Units(80))
-                                        )
-                                )
-                                .push(
-                                    Row::new()
-                                        .spacing(15)
-                                        .push(
-                                            Text::new("B")
-                                        )
-                                        .push(
-                                            Slider::new(fg_blue_slider, 0..=255, fg_blue_value, Message::FgBlueChanged).width(Length::Units(80))
-                                        )
-                                )
+                            Text::new("Quirks").size(20)
+                        )
+                        .push(
+                            PickList::new(quirks_preset_picker, &QuirksPreset::ALL[..], QuirksPreset::matching(&config.quirks), Message::QuirksPresetSelected)
+                        )
+                )
+                .push(
+                    Column::new()
+                        .spacing(5)
+                        .push(
+                            Checkbox::new(config.quirks.shift_reads_vy, "8XY6/8XYE shift VY", Message::ShiftQuirkToggled)
+                        )
+                        .push(
+                            Checkbox::new(config.quirks.load_store_increments_i, "FX55/FX65 increment I", Message::LoadStoreQuirkToggled)
+                        )
+                        .push(
+                            Checkbox::new(config.quirks.jump_uses_vx, "BNNN jumps with VX", Message::JumpQuirkToggled)
+                        )
+                        .push(
+                            Checkbox::new(config.quirks.logic_resets_vf, "8XY1-3 reset VF", Message::VfResetQuirkToggled)
+                        )
+                        .push(
+                            Checkbox::new(config.quirks.wrap_sprites, "DXYN wraps sprites", Message::WrapQuirkToggled)
                         )
                 )
         )
@@ -153,7 +219,7 @@ pub fn draw<'a>(rom_name: String,
                     Button::new(launch_button, Text::new(String::from("Launch ROM")))
                 } else {
                     Button::new(launch_button, Text::new(String::from("Launch ROM")))
-                        .on_press(Message::Goto(PageModel::EmulationScreen))
+                        .on_press(Message::Goto(PageModel::emulation_screen()))
             }
         );
     Container::new(content)
@@ -162,4 +228,33 @@ pub fn draw<'a>(rom_name: String,
         .center_x()
         .center_y()
         .into()
-}
\ No newline at end of file
+}
+/// Title, authors and description of a ROM the database recognised, whose settings have been
+/// applied below.
+fn rom_details<'a>(rom_info: Option<RomInfo>) -> Element<'a, Message> {
+    let info = match rom_info {
+        Some(info) => info,
+        None => return Column::new().into(),
+    };
+    let mut byline = info.authors.join(", ");
+    if let Some(release) = &info.release {
+        byline = if byline.is_empty() { release.clone() } else { format!("{} ({})", byline, release) };
+    }
+    Column::new()
+        .spacing(5)
+        .align_items(Align::Center)
+        .max_width(600)
+        .push(
+            Text::new(info.title).size(24)
+        )
+        .push(
+            Text::new(byline).size(16)
+        )
+        .push(
+            Text::new(info.description.unwrap_or_default()).size(16)
+        )
+        .push(
+            Text::new("Settings applied from the ROM database").size(14).color(Color::from_rgb(0.4, 0.4, 0.4))
+        )
+        .into()
+}
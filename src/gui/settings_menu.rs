@@ -0,0 +1,40 @@
+use iced::{slider, Align, Color, Column, Element, Length, Row, Slider, Text, VerticalAlignment};
+use crate::application::Message;
+
+const PALETTE_LABELS: [&str; 4] = ["Background", "Plane 1", "Plane 2", "Both Planes"];
+const CHANNEL_LABELS: [&str; 3] = ["R", "G", "B"];
+
+/// One RGB picker per palette entry. Plain CHIP-8 and SUPER-CHIP programs only use the first two.
+pub fn palette_picker<'a>(palette_sliders: &'a mut [[slider::State; 3]; 4],
+                          palette: [[u32; 3]; 4]) -> Element<'a, Message> {
+    let mut pickers = Column::new().spacing(10);
+    for (color_idx, channel_sliders) in palette_sliders.iter_mut().enumerate() {
+        let rgb = palette[color_idx];
+        let mut channels = Column::new();
+        for (channel_idx, channel_slider) in channel_sliders.iter_mut().enumerate() {
+            channels = channels.push(
+                Row::new()
+                    .spacing(15)
+                    .push(
+                        Text::new(CHANNEL_LABELS[channel_idx])
+                    )
+                    .push(
+                        Slider::new(channel_slider, 0..=255, rgb[channel_idx] as i32, move |val| Message::PaletteChanged(color_idx, channel_idx, val)).width(Length::Units(80))
+                    )
+            );
+        }
+        pickers = pickers.push(
+            Row::new()
+                .spacing(20)
+                .align_items(Align::Center)
+                .push(
+                    Text::new(format!("{} Color", PALETTE_LABELS[color_idx]))
+                        .color(Color::from_rgb8(rgb[0] as u8, rgb[1] as u8, rgb[2] as u8))
+                        .width(Length::Units(160))
+                        .vertical_alignment(VerticalAlignment::Center)
+                )
+                .push(channels)
+        );
+    }
+    pickers.into()
+}
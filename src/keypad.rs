@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use iced::keyboard as iced_keyboard;
+use tjr_chip8::database::KeyMapping;
 
 pub struct Keyboard {
     pub keys: [bool; 16],
@@ -31,4 +32,22 @@ impl Keyboard {
             key_map,
         }
     }
+
+    /// Restores the default layout and additionally maps the arrow keys, Space and Enter onto
+    /// the keypad keys a ROM uses for directions and its two action buttons.
+    pub fn map_game_keys(&mut self, keys: &KeyMapping) {
+        self.key_map = Self::new().key_map;
+        let game_keys = [
+            (iced_keyboard::KeyCode::Up, keys.up),
+            (iced_keyboard::KeyCode::Down, keys.down),
+            (iced_keyboard::KeyCode::Left, keys.left),
+            (iced_keyboard::KeyCode::Right, keys.right),
+            (iced_keyboard::KeyCode::Space, keys.a),
+            (iced_keyboard::KeyCode::Enter, keys.b)];
+        for (key_code, key) in game_keys.iter() {
+            if let Some(key) = key {
+                self.key_map.insert(*key_code, *key as usize);
+            }
+        }
+    }
 }
\ No newline at end of file
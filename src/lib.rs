@@ -0,0 +1,7 @@
+//! The CHIP-8 emulator core. This crate has no GUI or audio dependencies when built with
+//! `default-features = false`, so headless tools can embed the interpreter on its own.
+
+pub mod assembler;
+pub mod chip8;
+pub mod database;
+pub mod disassembler;
@@ -1,10 +1,31 @@
+#[cfg(feature = "gui")]
 use iced::Application;
 
+#[cfg(feature = "gui")]
 mod application;
+#[cfg(feature = "cli")]
+mod cli;
+#[cfg(feature = "gui")]
 mod gui;
+#[cfg(feature = "gui")]
 mod keypad;
-mod chip8;
+#[cfg(feature = "gui")]
+mod save_slots;
 
 fn main() {
+    //Any arguments select a headless subcommand; without them the window opens
+    #[cfg(feature = "cli")]
+    {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        if !args.is_empty() || cfg!(not(feature = "gui")) {
+            std::process::exit(cli::main(&args));
+        }
+    }
+    #[cfg(feature = "gui")]
     application::Chip8Emulator::run(iced::Settings::default());
+    #[cfg(not(any(feature = "gui", feature = "cli")))]
+    {
+        eprintln!("built without the gui and cli features; there is nothing to run");
+        std::process::exit(2);
+    }
 }
@@ -0,0 +1,51 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+pub const NUM_SLOTS: usize = 4;
+const SAVE_DIRECTORY: &str = "saves";
+
+/// A quick-save slot for the current ROM and when it was last written, if ever.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SaveSlot {
+    pub index: usize,
+    pub written: Option<SystemTime>,
+}
+
+impl fmt::Display for SaveSlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let age = match self.written.map(|t| SystemTime::now().duration_since(t).unwrap_or_default().as_secs()) {
+            None => return write!(f, "Slot {} - empty", self.index + 1),
+            Some(secs) if secs < 60 => String::from("just now"),
+            Some(secs) if secs < 60 * 60 => format!("{} min ago", secs / 60),
+            Some(secs) if secs < 24 * 60 * 60 => format!("{} h ago", secs / (60 * 60)),
+            Some(secs) => format!("{} days ago", secs / (24 * 60 * 60)),
+        };
+        write!(f, "Slot {} - saved {}", self.index + 1, age)
+    }
+}
+
+fn path(rom_name: &str, index: usize) -> PathBuf {
+    PathBuf::from(SAVE_DIRECTORY).join(format!("{}.{}.state", rom_name, index + 1))
+}
+
+/// Every slot for `rom_name`, filled or not.
+pub fn list(rom_name: &str) -> Vec<SaveSlot> {
+    (0..NUM_SLOTS)
+        .map(|index| SaveSlot {
+            index,
+            written: fs::metadata(path(rom_name, index)).and_then(|m| m.modified()).ok(),
+        })
+        .collect()
+}
+
+pub fn write(rom_name: &str, index: usize, state: &[u8]) -> io::Result<()> {
+    fs::create_dir_all(SAVE_DIRECTORY)?;
+    fs::write(path(rom_name, index), state)
+}
+
+pub fn read(rom_name: &str, index: usize) -> io::Result<Vec<u8>> {
+    fs::read(path(rom_name, index))
+}
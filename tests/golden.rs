@@ -0,0 +1,137 @@
+//! Golden-framebuffer regression tests. Each case runs a ROM from `tests/golden/roms` for a
+//! fixed number of frames under a fixed seed and quirk profile, then compares the screen with a
+//! text image in `tests/golden/expected` (see `Display::to_ascii` for the format).
+//!
+//! After an intentional change to what a ROM draws, regenerate the expected images with
+//!
+//! ```bash
+//! UPDATE_GOLDEN=1 cargo test --test golden
+//! ```
+//!
+//! and review the changed `.txt` files like any other diff. The ROMs are assembled from the
+//! `.8o` Octo sources next to them.
+
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+
+use tjr_chip8::chip8::{Chip8, Config, FrameScheduler, Platform, Quirks};
+
+const UPDATE_VAR: &str = "UPDATE_GOLDEN";
+const SEED: u64 = 0x0060_1DE4;
+const CLOCK_SPEED: u32 = 1000;
+const FRAMES: u32 = 60;
+/// Mismatching pixels listed before the rest are only counted.
+const MAX_REPORTED_PIXELS: usize = 40;
+
+fn golden_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden")
+}
+
+/// Runs `rom` and compares its screen with `expected/<name>.txt`, or rewrites that file when
+/// `UPDATE_GOLDEN` is set.
+fn check(name: &str, rom: &str, platform: Platform, quirks: Quirks) {
+    let rom = fs::read(golden_dir().join("roms").join(rom)).expect("unable to read test ROM");
+    let config = Config { quirks, seed: SEED, ..Config::new(platform) };
+    let mut chip8 = Chip8::new(&rom, config).expect("test ROM does not load");
+    let mut scheduler = FrameScheduler::new(CLOCK_SPEED);
+    for frame in 0..FRAMES {
+        if let Err(e) = scheduler.run_frame(&mut chip8, [false; 16]) {
+            panic!("{} stopped in frame {}: {}", name, frame, e);
+        }
+    }
+    let actual = chip8.screen().to_ascii();
+
+    let path = golden_dir().join("expected").join(format!("{}.txt", name));
+    if env::var_os(UPDATE_VAR).is_some() {
+        fs::write(&path, &actual).expect("unable to write expected image");
+        return;
+    }
+    let expected = fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!("no expected image at {}; run with {}=1 to create it", path.display(), UPDATE_VAR)
+    });
+    if actual != expected {
+        panic!("{} does not match {}\n{}", name, path.display(), diff(&expected, &actual));
+    }
+}
+
+/// Lists the mismatching pixels and shows each row that differs with the columns marked.
+fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    let size = |rows: &[&str]| (rows.first().map_or(0, |row| row.len()), rows.len());
+    let mut report = String::new();
+    if size(&expected) != size(&actual) {
+        let (ew, eh) = size(&expected);
+        let (aw, ah) = size(&actual);
+        writeln!(report, "expected a {}x{} screen, got {}x{}:", ew, eh, aw, ah).unwrap();
+        for row in &actual {
+            writeln!(report, "  {}", row).unwrap();
+        }
+        return report;
+    }
+
+    let mut pixels = Vec::new();
+    for (y, (expected_row, actual_row)) in expected.iter().zip(&actual).enumerate() {
+        if expected_row == actual_row {
+            continue;
+        }
+        let mut marker = String::new();
+        for (x, (e, a)) in expected_row.chars().zip(actual_row.chars()).enumerate() {
+            if e == a {
+                marker.push(' ');
+            } else {
+                marker.push('^');
+                pixels.push(format!("({}, {}): expected '{}', got '{}'", x, y, e, a));
+            }
+        }
+        writeln!(report, "row {}:", y).unwrap();
+        writeln!(report, "  expected {}", expected_row).unwrap();
+        writeln!(report, "  actual   {}", actual_row).unwrap();
+        writeln!(report, "           {}", marker.trim_end()).unwrap();
+    }
+    writeln!(report, "{} pixels differ:", pixels.len()).unwrap();
+    for pixel in pixels.iter().take(MAX_REPORTED_PIXELS) {
+        writeln!(report, "  {}", pixel).unwrap();
+    }
+    if pixels.len() > MAX_REPORTED_PIXELS {
+        writeln!(report, "  and {} more", pixels.len() - MAX_REPORTED_PIXELS).unwrap();
+    }
+    report
+}
+
+#[test]
+fn flags_cosmac_vip() {
+    check("flags_cosmac_vip", "flags.ch8", Platform::Chip8, Quirks::cosmac_vip());
+}
+
+#[test]
+fn flags_superchip() {
+    check("flags_superchip", "flags.ch8", Platform::SuperChip, Quirks::superchip());
+}
+
+#[test]
+fn sprites_clipped() {
+    check("sprites_clipped", "sprites.ch8", Platform::Chip8, Quirks::cosmac_vip());
+}
+
+#[test]
+fn sprites_wrapped() {
+    check("sprites_wrapped", "sprites.ch8", Platform::XoChip, Quirks::xochip());
+}
+
+#[test]
+fn hires_superchip() {
+    check("hires_superchip", "hires.ch8", Platform::SuperChip, Quirks::superchip());
+}
+
+#[test]
+fn random_seeded() {
+    check("random_seeded", "random.ch8", Platform::Chip8, Quirks::cosmac_vip());
+}
+
+#[test]
+fn planes_xochip() {
+    check("planes_xochip", "planes.ch8", Platform::XoChip, Quirks::xochip());
+}